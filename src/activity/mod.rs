@@ -0,0 +1,566 @@
+//! Project Activity
+//!
+//! Watches the directories of registered projects and records which files
+//! change, and when, per glyph under `activity/` in the working directory.
+//! Paths matched by the `.gitignore` files of a project, its
+//! `.git/info/exclude`, the global git excludes or the state-graph ignore
+//! list are left out, as are `.git` and the working directory itself. The
+//! watcher follows state-graph updates, picking up projects as they come and
+//! go. Activity files are locked while written, so that history edits and
+//! the watcher don't interleave. Nothing is recorded unless the watcher runs,
+//! which the daemon doesn't do for it: `godwit activity --watch` is meant to
+//! run alongside it.
+use crate::errors::ActivityError;
+use crate::glyph::Glyph;
+use crate::settings;
+use crate::statehandler::{self, State};
+use crate::tracker;
+use chrono::{DateTime, Local};
+use getter_derive::Getter;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use log::{debug, warn};
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Time changes to a file are gathered over before being recorded.
+pub const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Git ignore file name.
+pub const GITIGNORE_FILE: &str = ".gitignore";
+
+/// Repository exclude file, relative to the project directory.
+pub const EXCLUDE_FILE: &str = ".git/info/exclude";
+
+/// Kind of file change.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+	Created,
+	Modified,
+	Removed,
+	Renamed,
+}
+
+impl fmt::Display for ChangeKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ChangeKind::Created => write!(f, "created"),
+			ChangeKind::Modified => write!(f, "modified"),
+			ChangeKind::Removed => write!(f, "removed"),
+			ChangeKind::Renamed => write!(f, "renamed"),
+		}
+	}
+}
+
+/// Recorded file change in a project, its path relative to the project
+/// directory. Changes squashed in history span until their last one, under
+/// the common path of the files they touched, and may carry a note.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Getter)]
+#[serde(rename_all = "snake_case")]
+pub struct Change {
+	glyph: Glyph,
+	path: PathBuf,
+	kind: ChangeKind,
+	time: DateTime<Local>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	until: Option<DateTime<Local>>,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	squashed: Vec<PathBuf>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	note: Option<String>,
+}
+
+impl Change {
+	/// Returns a change of a file.
+	pub fn new(glyph: Glyph, path: PathBuf, kind: ChangeKind, time: DateTime<Local>) -> Self {
+		Change {
+			glyph: glyph,
+			path: path,
+			kind: kind,
+			time: time,
+			until: None,
+			squashed: Vec::new(),
+			note: None,
+		}
+	}
+
+	/// Squashes changes, oldest first, into one spanning them all.
+	pub fn squash(changes: &[Change], note: Option<String>) -> Option<Self> {
+		let (first, last) = (changes.first()?, changes.last()?);
+
+		let mut squashed: Vec<PathBuf> = Vec::new();
+
+		for change in changes {
+			let paths = if change.squashed.is_empty() {
+				vec![change.path.clone()]
+			} else {
+				change.squashed.clone()
+			};
+
+			for path in paths {
+				if !squashed.contains(&path) {
+					squashed.push(path);
+				}
+			}
+		}
+
+		let mut path = squashed[0].clone();
+
+		while !squashed.iter().all(|squashed| squashed.starts_with(&path)) {
+			path = path.parent().map(Path::to_path_buf).unwrap_or_default();
+		}
+
+		Some(Change {
+			glyph: first.glyph.clone(),
+			path: path,
+			kind: ChangeKind::Modified,
+			time: first.time,
+			until: Some(last.until.unwrap_or(last.time)),
+			squashed: squashed,
+			note: note,
+		})
+	}
+
+	/// Sets the note of a change.
+	pub fn annotate(&mut self, note: Option<String>) -> &mut Self {
+		self.note = note;
+		self
+	}
+}
+
+/// Watched project with its ignore rules. The `.gitignore` of each directory
+/// is loaded once needed.
+struct Project {
+	glyph: Glyph,
+	directory: PathBuf,
+	ignore: Gitignore,
+	excludes: Vec<Gitignore>,
+	gitignores: BTreeMap<PathBuf, Gitignore>,
+}
+
+/// Builds ignore rules rooted at a directory from one file, if usable.
+fn load_ignore_file(root: &Path, path: &Path) -> Gitignore {
+	let mut builder = GitignoreBuilder::new(root);
+
+	if let Some(e) = builder.add(path) {
+		debug!("No usable {}: {}", path.display(), e);
+	}
+
+	builder.build().unwrap_or_else(|e| {
+		warn!(
+			"Ignore rules of {} couldn't be built: {}",
+			path.display(),
+			e
+		);
+		Gitignore::empty()
+	})
+}
+
+impl Project {
+	/// Returns a watched project, loading its excludes, the global excludes
+	/// and the ignore list. Absolute ignore list entries only apply to
+	/// projects holding them.
+	fn load(glyph: Glyph, directory: PathBuf, ignore: &[PathBuf]) -> Self {
+		let (global, e) = GitignoreBuilder::new(&directory).build_global();
+
+		if let Some(e) = e {
+			debug!("Global git excludes aren't usable: {}", e);
+		}
+
+		let excludes = vec![
+			load_ignore_file(&directory, &directory.join(EXCLUDE_FILE)),
+			global,
+		];
+
+		let mut builder = GitignoreBuilder::new(&directory);
+
+		for entry in ignore {
+			let pattern = match entry.strip_prefix(&directory) {
+				Ok(relative) => format!("/{}", relative.display()),
+				Err(_) if entry.is_absolute() => continue,
+				Err(_) => entry.display().to_string(),
+			};
+
+			if let Err(e) = builder.add_line(None, &pattern) {
+				warn!("Ignore entry {} is malformed: {}", entry.display(), e);
+			}
+		}
+
+		let ignore = builder.build().unwrap_or_else(|e| {
+			warn!("Ignore rules of {} couldn't be built: {}", glyph, e);
+			Gitignore::empty()
+		});
+
+		Project {
+			glyph: glyph,
+			directory: directory,
+			ignore: ignore,
+			excludes: excludes,
+			gitignores: BTreeMap::new(),
+		}
+	}
+
+	/// Returns the `.gitignore` rules of a directory within the project.
+	fn gitignore(&mut self, dir: &Path) -> &Gitignore {
+		self.gitignores
+			.entry(dir.to_path_buf())
+			.or_insert_with(|| load_ignore_file(dir, &dir.join(GITIGNORE_FILE)))
+	}
+
+	/// Drops loaded `.gitignore` rules of a directory, once they change.
+	fn forget(&mut self, dir: &Path) {
+		self.gitignores.remove(dir);
+	}
+
+	/// Checks whether a path is ignored. Like git, the nearest `.gitignore`
+	/// matching it decides, then the excludes.
+	fn is_ignored(&mut self, path: &Path, relative: &Path) -> bool {
+		let is_dir = path.is_dir();
+
+		if self
+			.ignore
+			.matched_path_or_any_parents(relative, is_dir)
+			.is_ignore()
+		{
+			return true;
+		}
+
+		let dirs = relative
+			.ancestors()
+			.skip(1)
+			.map(|ancestor| self.directory.join(ancestor))
+			.collect::<Vec<_>>();
+
+		for dir in dirs {
+			let matched = self
+				.gitignore(&dir)
+				.matched_path_or_any_parents(path, is_dir);
+
+			if matched.is_ignore() {
+				return true;
+			} else if matched.is_whitelist() {
+				return false;
+			}
+		}
+
+		self.excludes.iter().any(|excludes| {
+			excludes
+				.matched_path_or_any_parents(relative, is_dir)
+				.is_ignore()
+		})
+	}
+
+	/// Returns the path of a change relative to the project, unless ignored.
+	fn relative(&mut self, path: &Path) -> Option<PathBuf> {
+		let relative = path.strip_prefix(&self.directory).ok()?.to_path_buf();
+
+		if relative.as_os_str().is_empty() || relative.starts_with(".git") {
+			return None;
+		}
+
+		if relative.ends_with(GITIGNORE_FILE) {
+			if let Some(dir) = path.parent() {
+				self.forget(dir);
+			}
+		}
+
+		if self.is_ignored(path, &relative) {
+			return None;
+		}
+
+		Some(relative)
+	}
+}
+
+/// Returns the activity directory.
+pub fn get_activity_dir() -> PathBuf {
+	settings::get_working_dir().join("activity")
+}
+
+/// Returns the activity file of a project.
+fn activity_path(glyph: &Glyph) -> PathBuf {
+	get_activity_dir()
+		.join(&glyph.tag)
+		.join(format!("{}.log", glyph.id))
+}
+
+/// Exclusive lock over the activity of a project, released once dropped.
+pub struct Lock {
+	_file: fs::File,
+}
+
+/// Locks the activity of a project, waiting for other holders.
+pub fn lock(glyph: &Glyph) -> Result<Lock, ActivityError> {
+	let path = activity_path(glyph).with_extension("lock");
+
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)?;
+	}
+
+	let file = OpenOptions::new()
+		.create(true)
+		.write(true)
+		.truncate(false)
+		.open(path)?;
+
+	#[cfg(unix)]
+	{
+		use std::os::unix::io::AsRawFd;
+
+		if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+			return Err(std::io::Error::last_os_error().into());
+		}
+	}
+
+	Ok(Lock { _file: file })
+}
+
+/// Appends a change to the activity of its project.
+pub fn record(change: &Change) -> Result<(), ActivityError> {
+	let _lock = lock(&change.glyph)?;
+	let path = activity_path(&change.glyph);
+
+	let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+	writeln!(file, "{}", serde_json::to_string(change)?)?;
+	Ok(())
+}
+
+/// Loads recorded changes of a project, oldest first. Malformed lines are
+/// skipped.
+pub fn load_changes(glyph: &Glyph) -> Result<Vec<Change>, ActivityError> {
+	let file = match fs::File::open(activity_path(glyph)) {
+		Ok(file) => file,
+		Err(_) => return Ok(Vec::new()),
+	};
+
+	let mut changes = Vec::new();
+
+	for line in BufReader::new(file).lines() {
+		if let Ok(change) = serde_json::from_str::<Change>(&line?) {
+			changes.push(change);
+		}
+	}
+
+	Ok(changes)
+}
+
+/// Replaces recorded changes of a project.
+pub fn save_changes(glyph: &Glyph, changes: &[Change]) -> Result<(), ActivityError> {
+	let _lock = lock(glyph)?;
+	write_changes(glyph, changes)
+}
+
+/// Updates recorded changes of a project, oldest first, holding the lock
+/// from loading to saving them.
+pub fn update_changes<F, T, E>(glyph: &Glyph, update: F) -> Result<T, E>
+where
+	F: FnOnce(&mut Vec<Change>) -> Result<T, E>,
+	E: From<ActivityError>,
+{
+	let _lock = lock(glyph)?;
+
+	let mut changes = load_changes(glyph)?;
+	changes.sort_by_key(|change| change.time);
+
+	let result = update(&mut changes)?;
+	write_changes(glyph, &changes)?;
+
+	Ok(result)
+}
+
+/// Swaps the activity file of a project whole. The lock must be held.
+fn write_changes(glyph: &Glyph, changes: &[Change]) -> Result<(), ActivityError> {
+	let path = activity_path(glyph);
+	let swap_path = path.with_extension("log.swap");
+
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)?;
+	}
+
+	let mut file = fs::File::create(&swap_path)?;
+
+	for change in changes {
+		writeln!(file, "{}", serde_json::to_string(change)?)?;
+	}
+
+	fs::rename(swap_path, path)?;
+	Ok(())
+}
+
+/// Lists projects with recorded activity.
+pub fn active_glyphs() -> Result<Vec<Glyph>, ActivityError> {
+	let mut glyphs = Vec::new();
+
+	let tag_dirs = match fs::read_dir(get_activity_dir()) {
+		Ok(tag_dirs) => tag_dirs,
+		Err(_) => return Ok(glyphs),
+	};
+
+	for tag_dir in tag_dirs.filter_map(|entry| entry.ok()) {
+		let tag = tag_dir.file_name().to_string_lossy().into_owned();
+
+		for path in fs::read_dir(tag_dir.path())?
+			.filter_map(|entry| entry.ok())
+			.map(|entry| entry.path())
+		{
+			if let Some(id) = path
+				.file_stem()
+				.filter(|_| path.extension().map_or(false, |ext| ext == "log"))
+			{
+				glyphs.push(Glyph {
+					tag: tag.clone(),
+					id: id.to_string_lossy().into_owned(),
+				});
+			}
+		}
+	}
+
+	Ok(glyphs)
+}
+
+/// Returns the last changes of a project, or of every project, oldest first.
+pub fn timeline(glyph: Option<Glyph>, limit: usize) -> Result<Vec<Change>, ActivityError> {
+	let glyphs = match glyph {
+		Some(glyph) => vec![glyph],
+		None => active_glyphs()?,
+	};
+
+	let mut changes = Vec::new();
+
+	for glyph in glyphs {
+		changes.extend(load_changes(&glyph)?);
+	}
+
+	changes.sort_by_key(|change| change.time);
+	Ok(changes.split_off(changes.len().saturating_sub(limit)))
+}
+
+/// Projects watched along with the active one.
+struct Watched {
+	projects: Vec<Project>,
+	active: Option<Glyph>,
+}
+
+/// Watches directories of registered projects, dropping previous ones.
+fn register(
+	watcher: &mut RecommendedWatcher,
+	previous: &Watched,
+) -> Result<Watched, ActivityError> {
+	for project in &previous.projects {
+		let _ = watcher.unwatch(&project.directory);
+	}
+
+	let state_graph = statehandler::load_stategraph()?;
+	let ignore = state_graph.get_ignore();
+	let mut projects = Vec::new();
+
+	for state in state_graph.get_states() {
+		let directory = match state.get_directory().filter(|directory| directory.is_dir()) {
+			Some(directory) => directory,
+			None => continue,
+		};
+
+		match watcher.watch(&directory, RecursiveMode::Recursive) {
+			Ok(_) => projects.push(Project::load(state.get_glyph(), directory, &ignore)),
+			Err(e) => warn!("{} couldn't be watched: {}", state.get_glyph(), e),
+		}
+	}
+
+	// Nested projects claim their own changes.
+	projects.sort_by_key(|project| std::cmp::Reverse(project.directory.components().count()));
+
+	Ok(Watched {
+		projects: projects,
+		active: state_graph.get_active().as_ref().map(State::get_glyph),
+	})
+}
+
+/// Returns the change a file event stands for, unless ignored.
+fn change_of(watched: &mut Watched, event: DebouncedEvent) -> Option<Change> {
+	let (path, kind) = match event {
+		DebouncedEvent::Create(path) => (path, ChangeKind::Created),
+		DebouncedEvent::Write(path) => (path, ChangeKind::Modified),
+		DebouncedEvent::Remove(path) => (path, ChangeKind::Removed),
+		DebouncedEvent::Rename(_, path) => (path, ChangeKind::Renamed),
+		DebouncedEvent::Error(e, path) => {
+			warn!("Watching {:?} failed: {}", path, e);
+			return None;
+		}
+		_ => return None,
+	};
+
+	if path.starts_with(settings::get_working_dir()) {
+		return None;
+	}
+
+	let project = watched
+		.projects
+		.iter_mut()
+		.find(|project| path.starts_with(&project.directory))?;
+
+	project
+		.relative(&path)
+		.map(|relative| Change::new(project.glyph.clone(), relative, kind, Local::now()))
+}
+
+/// Watches registered projects, recording changes and passing them on. The
+/// active project is marked seen as it changes. Blocks until the watcher
+/// fails.
+pub fn watch<F>(mut on_change: F) -> Result<(), ActivityError>
+where
+	F: FnMut(&Change),
+{
+	let (tx, rx) = mpsc::channel();
+	let mut watcher = notify::watcher(tx, DEBOUNCE)?;
+
+	let state_file = settings::get_settings()?.get_save_state()?;
+	let state_dir = state_file
+		.parent()
+		.map(Path::to_path_buf)
+		.unwrap_or_default();
+	watcher.watch(&state_dir, RecursiveMode::NonRecursive)?;
+
+	let mut watched = register(
+		&mut watcher,
+		&Watched {
+			projects: Vec::new(),
+			active: None,
+		},
+	)?;
+
+	for event in rx {
+		let is_state_change = match &event {
+			DebouncedEvent::Create(path) | DebouncedEvent::Write(path) => path == &state_file,
+			DebouncedEvent::Rename(_, path) => path == &state_file,
+			_ => false,
+		};
+
+		if is_state_change {
+			debug!("State-graph changed, watching projects anew.");
+			watched = register(&mut watcher, &watched)?;
+			continue;
+		}
+
+		if let Some(change) = change_of(&mut watched, event) {
+			if let Err(e) = record(&change) {
+				warn!("Change in {} couldn't be recorded: {}", change.glyph, e);
+			}
+
+			if watched.active.as_ref() == Some(&change.glyph) {
+				tracker::heartbeat(&change.glyph);
+			}
+
+			on_change(&change);
+		}
+	}
+
+	Ok(())
+}
@@ -4,61 +4,706 @@
 //! forward wrappers. It contains full API definitions and endpoints to
 //! integrate Godwit core.
 mod setup;
+pub mod template;
 
-use crate::core::setup::{setup_gw_dir, setup_init_state};
-use crate::errors::CoreError;
+use crate::activity::{self, Change};
+use crate::core::setup::{relocate_gw_dir, setup_gw_dir, setup_init_state};
+use crate::daemon;
+use crate::env;
+use crate::errors::{CoreError, PluginError, SettingsError, StateError};
+use crate::format::Format;
 use crate::glyph::Glyph;
-use crate::plugins;
+use crate::history::{self, Range};
+use crate::hooks::{dispatch, HookContext, Operation, Stage};
+use crate::iohandler::scanner;
+use crate::plugins::audit::{self, Entry};
+use crate::plugins::command::Placeholders;
+use crate::plugins::manage::{self, Health};
+use crate::plugins::{self, discovery, Event, Hook, PluginConfig};
+use crate::scaffold;
+use crate::secrets::{self, Vault};
+use crate::settings::layers::{self, Origin};
+use crate::settings::{self, trust, Settings};
 use crate::statehandler::{self, State};
+use crate::tracker::{self, Period, Row};
 use crate::tui;
-use std::path::PathBuf;
+use chrono::NaiveDate;
+use log::{info, warn};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env as std_env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, ExitStatus};
+
+/// One-time Godwit setup call, optionally from a template.
+pub fn init(
+	path: Option<PathBuf>,
+	headless: bool,
+	refresh: bool,
+	template: Option<String>,
+) -> Result<(), CoreError> {
+	// Pre-init hooks can only come from settings already in place.
+	if settings::get_settings().is_ok() {
+		dispatch(
+			Stage::Pre,
+			Operation::Init,
+			&HookContext {
+				old_dir: Some(settings::get_working_dir()),
+				..Default::default()
+			},
+			&[],
+			None,
+		)?;
+	}
+
+	let template = setup_gw_dir(path, headless, refresh, template)?;
+	setup_init_state(template.as_ref())?;
+
+	let settings = settings::get_settings()?;
+
+	dispatch(
+		Stage::Post,
+		Operation::Init,
+		&HookContext {
+			new_dir: settings.get_working_dir(),
+			..Default::default()
+		},
+		&[],
+		Some(Event::Init(&settings)),
+	)?;
 
-/// One-time Godwit setup call.
-pub fn init(path: Option<PathBuf>, headless: bool, refresh: bool) -> Result<(), CoreError> {
-	setup_gw_dir(path, headless, refresh)?;
-	setup_init_state()?;
 	Ok(())
 }
 
-/// Add project to Godwit.
+/// Find a state by exact glyph.
+fn find_state(glyph: &Glyph) -> Result<State, CoreError> {
+	let state = statehandler::load_stategraph()?
+		.search_states(glyph.to_string(), false)
+		.ok_or_else(|| StateError::StateNotFound {
+			state: glyph.to_string(),
+		})?;
+	Ok(state)
+}
+
+/// List effective settings with their origin.
+pub fn show_config() -> Result<Vec<(String, Value, Origin)>, CoreError> {
+	let entries = settings::resolve_settings()?.entries();
+	Ok(entries)
+}
+
+/// Get effective value of a settings key.
+pub fn get_config(key: String) -> Result<Value, CoreError> {
+	let value = settings::get_setting(&key)?;
+	Ok(value)
+}
+
+/// Set a settings key in user settings.
+pub fn set_config(key: String, value: String) -> Result<(), CoreError> {
+	settings::set_setting(&key, &value)?;
+	Ok(())
+}
+
+/// Trust project settings to set hooks, plugins, env and directories, or revoke that trust.
+/// Defaults to the nearest project settings.
+pub fn trust_config(file: Option<PathBuf>, revoke: bool) -> Result<PathBuf, CoreError> {
+	let file = match file {
+		Some(file) => file,
+		None => layers::find_project_settings().ok_or_else(|| SettingsError::SettingsNotFound {
+			file: layers::PROJECT_SETTINGS.to_string(),
+		})?,
+	};
+
+	let path = if revoke {
+		trust::revoke(&file)?
+	} else {
+		trust::trust(&file)?
+	};
+	Ok(path)
+}
+
+/// Validate a settings file, defaulting to user settings.
+pub fn validate_config(file: Option<PathBuf>) -> Result<Settings, CoreError> {
+	let file = match file {
+		Some(file) => file,
+		None => settings::get_user_settings_path()?,
+	};
+	let settings = settings::validate_settings(&file)?;
+	Ok(settings)
+}
+
+/// Edit user settings in $EDITOR, validating before saving.
+pub fn edit_config() -> Result<(), CoreError> {
+	let settings_path = settings::get_user_settings_path()?;
+
+	// The draft keeps the settings file extension, which decides its format.
+	let mut draft_name = std::ffi::OsString::from(format!("edit.{}.", process::id()));
+	draft_name.push(settings_path.file_name().unwrap_or_default());
+	let draft_path = std_env::temp_dir().join(draft_name);
+
+	fs::copy(&settings_path, &draft_path)?;
+
+	let editor = ["VISUAL", "EDITOR"]
+		.iter()
+		.filter_map(|var| std_env::var(var).ok())
+		.find(|editor| !editor.trim().is_empty())
+		.unwrap_or_else(|| String::from("vi"));
+	let editor_words = shell_words::split(&editor).unwrap_or_default();
+	let (editor_command, editor_args) = match editor_words.split_first() {
+		Some(editor_words) => editor_words,
+		None => {
+			fs::remove_file(&draft_path)?;
+			return Err(CoreError::InvalidEditor { editor: editor });
+		}
+	};
+
+	loop {
+		let status = Command::new(editor_command)
+			.args(editor_args)
+			.arg(&draft_path)
+			.status()?;
+
+		if !status.success() {
+			fs::remove_file(&draft_path)?;
+			return Err(CoreError::EditorFailed {
+				editor: editor_command.clone(),
+				status: status.to_string(),
+			});
+		}
+
+		match settings::validate_settings(&draft_path) {
+			Ok(_) => {
+				fs::copy(&draft_path, &settings_path)?;
+				fs::remove_file(&draft_path)?;
+				return Ok(());
+			}
+			Err(e) => {
+				println!("{}", e);
+
+				if !scanner::confirm("Edit again?")? {
+					fs::remove_file(&draft_path)?;
+					return Err(e.into());
+				}
+			}
+		}
+	}
+}
+
+/// Convert user settings and state-graphs to another file format.
+pub fn convert(target_format: Format) -> Result<Vec<PathBuf>, CoreError> {
+	let mut converted = vec![settings::convert_settings(target_format)?];
+
+	if !settings::get_settings()?.get_headless() {
+		converted.extend(statehandler::convert_stategraphs(target_format)?);
+	}
+
+	Ok(converted)
+}
+
+/// Fails operations that need a working directory in headless mode.
+fn require_working_dir(operation: &str) -> Result<(), CoreError> {
+	if settings::get_settings()?.get_headless() {
+		return Err(CoreError::DisallowedHeadless {
+			operation: operation.to_string(),
+		});
+	}
+	Ok(())
+}
+
+/// Move Godwit working directory.
+pub fn relocate(target: PathBuf) -> Result<(), CoreError> {
+	require_working_dir("relocate")?;
+	relocate_gw_dir(target)?;
+	Ok(())
+}
+
+/// Add project to Godwit, scaffolding it unless it exists.
 pub fn add(
 	glyph: Glyph,
 	location: PathBuf,
+	env: BTreeMap<String, String>,
 	existing: bool,
+	template: Option<String>,
 	active: bool,
 	default: bool,
 ) -> Result<(), CoreError> {
-	if !existing {
-		plugins::invoke("Weaver", None)?;
-	}
+	register(glyph, location, env, active, default, |glyph, location| {
+		if !existing {
+			plugins::weave(glyph, location, template.as_deref())?;
+		}
+		Ok(())
+	})
+}
+
+/// Scaffold a new project from a template directory and add it to Godwit.
+pub fn new(
+	glyph: Glyph,
+	location: PathBuf,
+	template: String,
+	values: BTreeMap<String, String>,
+	env: BTreeMap<String, String>,
+	active: bool,
+	default: bool,
+) -> Result<(), CoreError> {
+	register(glyph, location, env, active, default, |glyph, location| {
+		scaffold::scaffold(glyph, location, &template, values)?;
+		Ok(())
+	})
+}
+
+/// Add a state around add hooks, after preparing its location.
+fn register<F>(
+	glyph: Glyph,
+	location: PathBuf,
+	env: BTreeMap<String, String>,
+	active: bool,
+	default: bool,
+	prepare: F,
+) -> Result<(), CoreError>
+where
+	F: FnOnce(&Glyph, &Path) -> Result<(), CoreError>,
+{
+	let context = HookContext {
+		glyph: Some(glyph.clone()),
+		new_dir: Some(location.clone()),
+		active: statehandler::get_active()
+			.ok()
+			.map(|active| active.get_glyph()),
+		..Default::default()
+	};
+
+	dispatch(Stage::Pre, Operation::Add, &context, &[], None)?;
+
+	prepare(&glyph, &location)?;
+
+	statehandler::add_state(glyph.clone(), location, None, env, active, default)?;
+
+	let state = find_state(&glyph)?;
+
+	dispatch(
+		Stage::Post,
+		Operation::Add,
+		&HookContext {
+			active: statehandler::get_active()
+				.ok()
+				.map(|active| active.get_glyph()),
+			..context
+		},
+		&[&state],
+		Some(Event::Add(&state)),
+	)?;
 
-	statehandler::add_state(glyph, location, None, active, default)?;
 	Ok(())
 }
 
 /// Remove project from Godwit
 pub fn remove(glyph: Glyph) -> Result<(), CoreError> {
+	let state = statehandler::load_stategraph()?
+		.search_states(glyph.to_string(), true)
+		.ok_or_else(|| StateError::StateNotFound {
+			state: glyph.to_string(),
+		})?;
+
+	let context = HookContext {
+		glyph: Some(state.get_glyph()),
+		old_dir: state.get_directory(),
+		active: statehandler::get_active()
+			.ok()
+			.map(|active| active.get_glyph()),
+		..Default::default()
+	};
+
+	dispatch(Stage::Pre, Operation::Remove, &context, &[&state], None)?;
+
 	statehandler::purge_state(glyph)?;
+
+	dispatch(
+		Stage::Post,
+		Operation::Remove,
+		&HookContext {
+			active: statehandler::get_active()
+				.ok()
+				.map(|active| active.get_glyph()),
+			..context
+		},
+		&[&state],
+		Some(Event::Remove(&state)),
+	)?;
+
+	Ok(())
+}
+
+/// Add a plugin to user settings.
+pub fn add_plugin(
+	name: String,
+	exec: String,
+	hooks: Vec<Hook>,
+	protocol: bool,
+	timeout: Option<u64>,
+) -> Result<(), CoreError> {
+	let mut plugin = plugins::new(&name, &exec);
+	plugin.hooks(hooks).protocol(protocol).timeout(timeout);
+
+	manage::add_plugin(plugin)?;
+	Ok(())
+}
+
+/// List configured plugins along with their health.
+pub fn list_plugins(check: bool) -> Result<Vec<(PluginConfig, Health)>, CoreError> {
+	Ok(manage::list_plugins(check)?)
+}
+
+/// Enable or disable a plugin.
+pub fn enable_plugin(name: String, enabled: bool) -> Result<(), CoreError> {
+	manage::set_enabled(&name, enabled)?;
+	Ok(())
+}
+
+/// Remove a plugin from user settings.
+pub fn remove_plugin(name: String) -> Result<(), CoreError> {
+	manage::remove_plugin(&name)?;
 	Ok(())
 }
 
+/// List recorded plugin runs, optionally failed ones alone.
+pub fn plugin_log(failed: bool) -> Result<Vec<Entry>, CoreError> {
+	Ok(audit::read_log(failed)?)
+}
+
+/// Call a plugin directly.
+pub fn call_plugin(name: String, args: Vec<String>) -> Result<Option<Value>, CoreError> {
+	Ok(manage::call(&name, args)?)
+}
+
 /// List projects under Godwit.
 pub fn list() -> Result<Vec<State>, CoreError> {
-	let states = statehandler::load_stategraph()?.get_states().to_vec();
-	Ok(states)
+	let state_graph = match daemon::state_graph() {
+		Some(state_graph) => state_graph,
+		None => statehandler::load_stategraph()?,
+	};
+
+	if let Some(active) = state_graph.get_active() {
+		tracker::heartbeat(&active.get_glyph());
+	}
+
+	dispatch(
+		Stage::Post,
+		Operation::Status,
+		&HookContext::default(),
+		&[],
+		Some(Event::Status(&state_graph)),
+	)?;
+
+	Ok(state_graph.get_states())
 }
 
 /// Switch to another project under Godwit.
 pub fn switch(glyph: Glyph, default: bool) -> Result<(), CoreError> {
+	let prev_state = statehandler::get_active().ok();
+	let prev_states = prev_state.iter().collect::<Vec<_>>();
+
+	let state = statehandler::load_stategraph()?
+		.search_states(glyph.to_string(), true)
+		.ok_or_else(|| StateError::StateNotFound {
+			state: glyph.to_string(),
+		})?;
+
+	let context = HookContext {
+		glyph: Some(state.get_glyph()),
+		old_dir: prev_state.as_ref().and_then(State::get_directory),
+		new_dir: state.get_directory(),
+		active: prev_state.as_ref().map(State::get_glyph),
+	};
+
+	if prev_state.is_some() {
+		dispatch(Stage::Pre, Operation::Leave, &context, &prev_states, None)?;
+	}
+	dispatch(Stage::Pre, Operation::Switch, &context, &[&state], None)?;
+
 	statehandler::set_active(glyph.clone())?;
 
 	if default {
 		statehandler::set_default(glyph.clone())?;
 	}
 
+	let context = HookContext {
+		active: Some(state.get_glyph()),
+		..context
+	};
+
+	if prev_state.is_some() {
+		dispatch(Stage::Post, Operation::Leave, &context, &prev_states, None)?;
+	}
+
+	let active = statehandler::get_active()?;
+
+	dispatch(
+		Stage::Post,
+		Operation::Switch,
+		&context,
+		&[&state],
+		Some(Event::Switch(prev_state.as_ref(), &active)),
+	)?;
+
+	Ok(())
+}
+
+/// Set or clear a project environment variable.
+pub fn set_env(glyph: Glyph, key: String, value: Option<String>) -> Result<(), CoreError> {
+	statehandler::set_state_env(glyph, key, value)?;
 	Ok(())
 }
 
+/// Resolve target glyph, falling back to the active project.
+fn resolve_glyph(glyph: Option<Glyph>) -> Result<Glyph, CoreError> {
+	match glyph {
+		Some(glyph) => Ok(glyph),
+		None => Ok(statehandler::get_active()?.get_glyph()),
+	}
+}
+
+/// Store a project secret, read from a hidden prompt or piped stdin.
+pub fn set_secret(glyph: Option<Glyph>, name: String) -> Result<(), CoreError> {
+	require_working_dir("secret")?;
+	let glyph = resolve_glyph(glyph)?;
+	let value = secrets::read_value(&name)?;
+	let mut vault = Vault::unlock()?;
+	vault.set(&glyph, &name, &value)?;
+	vault.save()?;
+	Ok(())
+}
+
+/// Retrieve a project secret.
+pub fn get_secret(glyph: Option<Glyph>, name: String) -> Result<String, CoreError> {
+	require_working_dir("secret")?;
+	let glyph = resolve_glyph(glyph)?;
+	let value = Vault::unlock()?.get(&glyph, &name)?;
+	Ok(value)
+}
+
+/// Remove a project secret.
+pub fn remove_secret(glyph: Option<Glyph>, name: String) -> Result<(), CoreError> {
+	require_working_dir("secret")?;
+	let glyph = resolve_glyph(glyph)?;
+	let mut vault = Vault::unlock()?;
+	vault.remove(&glyph, &name)?;
+	vault.save()?;
+	Ok(())
+}
+
+/// List names of project secrets.
+pub fn list_secrets(glyph: Option<Glyph>) -> Result<Vec<String>, CoreError> {
+	require_working_dir("secret")?;
+	let glyph = resolve_glyph(glyph)?;
+	let names = Vault::unlock()?.list(&glyph);
+	Ok(names)
+}
+
+/// Run a command in the active project with its environment and secrets.
+pub fn run(command: Vec<String>) -> Result<ExitStatus, CoreError> {
+	let active = statehandler::get_active()?;
+	let directory = active.get_directory().unwrap_or_default();
+
+	tracker::heartbeat(&active.get_glyph());
+
+	let status = Command::new(&command[0])
+		.args(&command[1..])
+		.current_dir(&directory)
+		.env("GPD", &directory)
+		.envs(statehandler::get_state_env(&active)?)
+		.envs(secrets::export_secrets(&active.get_glyph())?)
+		.status()?;
+
+	Ok(status)
+}
+
+/// Run a command in every project with its environment and secrets. Returns
+/// the status of each run, going on past failures.
+pub fn foreach(command: Vec<String>) -> Result<Vec<(Glyph, ExitStatus)>, CoreError> {
+	let states = statehandler::load_stategraph()?.get_states();
+	let glyphs = states.iter().map(State::get_glyph).collect::<Vec<_>>();
+	let vault = secrets::unlock_for(&glyphs)?;
+
+	let mut statuses = Vec::new();
+
+	for state in states {
+		let glyph = state.get_glyph();
+		let directory = match state.get_directory().filter(|directory| directory.is_dir()) {
+			Some(directory) => directory,
+			None => {
+				warn!("Skipping {}, its directory is missing.", glyph);
+				continue;
+			}
+		};
+
+		let secrets = match &vault {
+			Some(vault) => vault.export(&glyph)?,
+			None => Default::default(),
+		};
+
+		info!("Running in {}", glyph);
+
+		let status = Command::new(&command[0])
+			.args(&command[1..])
+			.current_dir(&directory)
+			.env("GPD", &directory)
+			.envs(statehandler::get_state_env(&state)?)
+			.envs(secrets)
+			.status()?;
+
+		statuses.push((glyph, status));
+	}
+
+	Ok(statuses)
+}
+
+/// Print shell exports of the active project environment and secrets.
+pub fn shell_init() -> Result<(), CoreError> {
+	let active = statehandler::get_active()?;
+
+	env::set_export_only(true);
+
+	if let Some(directory) = active.get_directory() {
+		env::set_env_var(env::Var::GPD, directory.to_string_lossy())?;
+	}
+
+	let mut vars = statehandler::get_state_env(&active)?;
+	vars.extend(secrets::export_secrets(&active.get_glyph())?);
+
+	for (key, value) in vars {
+		env::set_env_var(env::Var::Custom(key), value)?;
+	}
+
+	Ok(())
+}
+
+/// Forward an unknown subcommand to its discovered `godwit-<name>` plugin.
+pub fn external(command: Vec<String>) -> Result<ExitStatus, CoreError> {
+	let plugin = discovery::find(&command[0]).ok_or_else(|| PluginError::PluginNotFound {
+		plugin: format!("{}{}", discovery::PREFIX, command[0]),
+	})?;
+
+	let status = plugin
+		.command(&Placeholders::from_args(&command[1..]))?
+		.env("GODWIT_WORKING_DIR", settings::get_working_dir())
+		.status()?;
+
+	Ok(status)
+}
+
+/// Report time spent on projects.
+pub fn time_report(
+	glyph: Option<Glyph>,
+	since: Option<NaiveDate>,
+	by: Option<Period>,
+) -> Result<Vec<Row>, CoreError> {
+	require_working_dir("time")?;
+	Ok(tracker::report(glyph, since, by)?)
+}
+
+/// Get the last file changes of a project, or of every project.
+pub fn activity(glyph: Option<Glyph>, limit: usize) -> Result<Vec<Change>, CoreError> {
+	require_working_dir("activity")?;
+	Ok(activity::timeline(glyph, limit)?)
+}
+
+/// Watch registered projects, recording file changes and passing them on.
+pub fn watch_activity<F>(on_change: F) -> Result<(), CoreError>
+where
+	F: FnMut(&Change),
+{
+	require_working_dir("activity")?;
+	activity::watch(on_change)?;
+	Ok(())
+}
+
+/// Get the tracked history of a project.
+pub fn history(glyph: Glyph) -> Result<Vec<Change>, CoreError> {
+	require_working_dir("history")?;
+	Ok(history::load(&glyph)?)
+}
+
+/// Drop a range of entries from the history of a project.
+pub fn snip_history(glyph: Glyph, range: Range) -> Result<usize, CoreError> {
+	require_working_dir("history")?;
+	Ok(history::snip(&glyph, range)?)
+}
+
+/// Squash a range of entries in the history of a project into one.
+pub fn squash_history(
+	glyph: Glyph,
+	range: Range,
+	note: Option<String>,
+) -> Result<usize, CoreError> {
+	require_working_dir("history")?;
+	Ok(history::squash(&glyph, range, note)?)
+}
+
+/// Note a range of entries in the history of a project.
+pub fn annotate_history(
+	glyph: Glyph,
+	range: Range,
+	note: Option<String>,
+) -> Result<usize, CoreError> {
+	require_working_dir("history")?;
+	Ok(history::annotate(&glyph, range, note)?)
+}
+
+/// Prune history of every project by retention rules.
+pub fn prune_history() -> Result<usize, CoreError> {
+	require_working_dir("history")?;
+	Ok(history::prune()?)
+}
+
+/// Start the daemon. Returns its pid.
+pub fn start_daemon() -> Result<u32, CoreError> {
+	require_working_dir("daemon")?;
+	Ok(daemon::start()?)
+}
+
+/// Stop the daemon.
+pub fn stop_daemon() -> Result<(), CoreError> {
+	daemon::stop()?;
+	Ok(())
+}
+
+/// Get daemon status.
+pub fn daemon_status() -> Result<daemon::Status, CoreError> {
+	Ok(daemon::status()?)
+}
+
+/// Trace the state of an application through the daemon.
+pub fn daemon_trace(application: String, refresh: bool) -> Result<(), CoreError> {
+	daemon::trace(&application, refresh)?;
+	Ok(())
+}
+
+/// Watch project events the daemon is notified of.
+pub fn daemon_events<F>(on_event: F) -> Result<(), CoreError>
+where
+	F: FnMut(daemon::Notification),
+{
+	daemon::watch(on_event)?;
+	Ok(())
+}
+
+/// Get last lines of the daemon log.
+pub fn daemon_logs(lines: usize) -> Result<Vec<String>, CoreError> {
+	Ok(daemon::logs(lines)?)
+}
+
+/// Restore shell configuration files from Godwit backups.
+pub fn restore_env(file: Option<PathBuf>) -> Result<Vec<PathBuf>, CoreError> {
+	let restored = match file {
+		Some(file) => {
+			env::restore_backup(&file)?;
+			vec![file]
+		}
+		None => env::restore_cfg_backups()?,
+	};
+	Ok(restored)
+}
+
 /// Forward to splash TUI.
 pub fn runsplash() -> Result<(), CoreError> {
 	tui::run()?;
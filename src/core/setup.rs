@@ -4,18 +4,47 @@
 //! unit with the core. It contains additional API calls for templated
 //! setups.
 
+use crate::core::template::{self, Template};
 use crate::errors::{SettingsError, SettingsMode, SetupError, StateError};
 use crate::settings::{self, Settings};
-use crate::statehandler::{self, StateGraph};
-use log::info;
-use std::path::PathBuf;
+use crate::statehandler;
+use log::{info, warn};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-/// Setup working directory and initialize settings.
+/// Setup working directory and initialize settings, optionally from a
+/// template. Returns the template for state-graph setup.
 pub fn setup_gw_dir(
 	cfgdir: Option<PathBuf>,
 	headless: bool,
 	refresh: bool,
-) -> Result<(), SetupError> {
+	template: Option<String>,
+) -> Result<Option<Template>, SetupError> {
+	let cfgdir = match cfgdir {
+		Some(cfgdir) => Some(env::current_dir()?.join(cfgdir)),
+		None => None,
+	};
+
+	if cfgdir.is_some() && !headless {
+		settings::set_config_path(cfgdir.clone());
+	}
+
+	let template = template
+		.map(|template| template::load_template(&template))
+		.transpose()?;
+
+	let working_dir = if headless {
+		settings::get_home_dir()
+	} else {
+		settings::get_working_dir()
+	};
+
+	let template_settings = match &template {
+		Some(template) => template.settings(&working_dir)?,
+		None => None,
+	};
+
 	if refresh {
 		settings::purge_settings(true)?
 	}
@@ -34,13 +63,110 @@ pub fn setup_gw_dir(
 		})
 		.or_else(|_| {
 			info!("Creating working directory at {:?}", cfgdir);
-			Settings::init(cfgdir, None, headless, true, None).save_settings(true)
-		})
-		.map_err(Into::into)
+
+			let bind_target = cfgdir.clone().filter(|_| !headless);
+
+			if let Some(target) = &bind_target {
+				settings::check_bindable(target)?;
+			}
+
+			match &template_settings {
+				Some(template_settings) => {
+					Settings::from_template(template_settings.clone(), cfgdir.clone(), headless)
+						.save_settings(true)?
+				}
+				None => Settings::init(cfgdir.clone(), None, headless, true, None)
+					.save_settings(true)?,
+			}
+
+			match &bind_target {
+				Some(target) => settings::bind_working_dir(target),
+				None => Ok(()),
+			}
+		})?;
+
+	Ok(template)
+}
+
+/// Move working directory to a new location and rebind the default location
+/// to it.
+pub fn relocate_gw_dir(target: PathBuf) -> Result<(), SetupError> {
+	let mut settings = settings::get_user_settings()?;
+
+	if settings.get_headless() {
+		return Err(SettingsError::DisallowedHeadless.into());
+	}
+
+	let target = env::current_dir()?.join(target);
+
+	if target.exists() {
+		return Err(SetupError::RelocationTargetExists {
+			path: target.to_string_lossy().into_owned(),
+		});
+	}
+
+	let link = settings::get_default_working_dir();
+	let via_default = settings::get_working_dir() == link;
+	let working_dir = settings::get_working_dir().canonicalize()?;
+
+	if via_default && fs::symlink_metadata(&link)?.file_type().is_symlink() {
+		fs::remove_file(&link)?;
+	}
+
+	info!(
+		"Moving working directory from {} to {}.",
+		working_dir.display(),
+		target.display()
+	);
+	move_dir(&working_dir, &target)?;
+
+	settings::set_config_path(Some(target.clone()));
+	settings.relocate(target.clone()).save_settings(true)?;
+
+	if via_default {
+		settings::bind_working_dir(&target)?;
+	} else {
+		warn!(
+			"Working directory wasn't reached through {}. Point GODWIT_HOME or --config to {}.",
+			link.display(),
+			target.display()
+		);
+	}
+
+	Ok(())
 }
 
-/// Setup state files and initialize state-graph.
-pub fn setup_init_state() -> Result<(), SetupError> {
+/// Moves a directory, copying across filesystems when renaming fails.
+fn move_dir(source: &Path, target: &Path) -> Result<(), SetupError> {
+	if fs::rename(source, target).is_ok() {
+		return Ok(());
+	}
+
+	copy_dir(source, target)?;
+	fs::remove_dir_all(source)?;
+	Ok(())
+}
+
+/// Recursively copies a directory tree.
+fn copy_dir(source: &Path, target: &Path) -> Result<(), SetupError> {
+	fs::create_dir_all(target)?;
+
+	for entry in fs::read_dir(source)? {
+		let entry = entry?;
+		let entry_target = target.join(entry.file_name());
+
+		if entry.file_type()?.is_dir() {
+			copy_dir(&entry.path(), &entry_target)?;
+		} else {
+			fs::copy(entry.path(), entry_target)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Setup state files and initialize state-graph, optionally from a template.
+pub fn setup_init_state(template: Option<&Template>) -> Result<(), SetupError> {
 	if statehandler::load_stategraph().is_ok() {
 		info!("Found an existing stategraph. Pass killsave option to overwrite.");
 		return Err(StateError::StateGraphAlreadyExists.into());
@@ -48,7 +174,13 @@ pub fn setup_init_state() -> Result<(), SetupError> {
 
 	info!("Creating new stategraph...");
 
-	StateGraph::default()
+	let state_graph = match template {
+		Some(template) => template.state_graph(&settings::get_working_dir())?,
+		None => None,
+	};
+
+	state_graph
+		.unwrap_or_default()
 		.propagate()
 		.and_then(|_| {
 			info!(
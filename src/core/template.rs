@@ -0,0 +1,248 @@
+//! Setup Templates
+//!
+//! Settings and state-graph templates used on setup. A template is either a
+//! directory holding `settings.gwcore` and/or `active.gwsg` (in any supported
+//! format) or a single one of these files. Templates are looked up as a path,
+//! then under `templates/` in the working directory, then among the bundled
+//! ones.
+//!
+//! String values may refer to `{{home}}`, `{{user}}` and `{{working_dir}}`,
+//! which are substituted before validation.
+use crate::errors::{FormatError, SetupError};
+use crate::format::{self, Format};
+use crate::settings::{self, Settings, SETTINGS_FILE, STATE_GRAPH_FILE};
+use crate::statehandler::StateGraph;
+use log::info;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Templates shipped with Godwit as (name, settings, state-graph).
+const BUNDLED: &[(&str, &str, &str)] = &[(
+	"default",
+	include_str!("../../assets/templates/settings.gwcore"),
+	include_str!("../../assets/templates/default.gwsg"),
+)];
+
+/// Template sources, not yet substituted or validated.
+#[derive(Clone, Debug, Default)]
+pub struct Template {
+	name: String,
+	settings: Option<(String, Format)>,
+	state_graph: Option<(String, Format)>,
+}
+
+impl Template {
+	/// Reads a template directory.
+	fn from_dir(name: &str, dir: &Path) -> Result<Self, SetupError> {
+		let mut template = Template {
+			name: name.to_string(),
+			..Default::default()
+		};
+
+		if let Some(path) = format::find_variant(&dir.join(SETTINGS_FILE)) {
+			template.settings = Some(read_source(&path)?);
+		}
+
+		if let Some(path) = format::find_variant(&dir.join(STATE_GRAPH_FILE)) {
+			template.state_graph = Some(read_source(&path)?);
+		}
+
+		if template.settings.is_none() && template.state_graph.is_none() {
+			return Err(SetupError::InvalidTemplate {
+				template: name.to_string(),
+				message: format!(
+					"{} holds neither {} nor {}",
+					dir.display(),
+					SETTINGS_FILE,
+					STATE_GRAPH_FILE
+				),
+			});
+		}
+
+		Ok(template)
+	}
+
+	/// Reads a single settings or state-graph template file.
+	fn from_file(name: &str, path: &Path) -> Result<Self, SetupError> {
+		let file_name = format::strip_format(path)
+			.file_name()
+			.unwrap_or_default()
+			.to_string_lossy()
+			.into_owned();
+
+		let mut template = Template {
+			name: name.to_string(),
+			..Default::default()
+		};
+
+		if file_name.ends_with(".gwcore") {
+			template.settings = Some(read_source(path)?);
+		} else if file_name.ends_with(".gwsg") {
+			template.state_graph = Some(read_source(path)?);
+		} else {
+			return Err(SetupError::InvalidTemplate {
+				template: name.to_string(),
+				message: String::from("template files must be .gwcore or .gwsg files"),
+			});
+		}
+
+		Ok(template)
+	}
+
+	/// Returns the substituted and validated settings, if templated.
+	pub fn settings(&self, working_dir: &Path) -> Result<Option<Settings>, SetupError> {
+		self.settings
+			.as_ref()
+			.map(|source| self.render(source, working_dir))
+			.transpose()
+	}
+
+	/// Returns the substituted and validated state-graph, if templated.
+	pub fn state_graph(&self, working_dir: &Path) -> Result<Option<StateGraph>, SetupError> {
+		self.state_graph
+			.as_ref()
+			.map(|source| {
+				let mut state_graph: StateGraph = self.render(source, working_dir)?;
+
+				state_graph
+					.validate()
+					.map_err(|e| SetupError::InvalidTemplate {
+						template: self.name.clone(),
+						message: e.to_string(),
+					})?;
+
+				// Active and default states may be given by glyph alone.
+				for state in state_graph.get_states() {
+					state_graph.update_state(state);
+				}
+
+				Ok(state_graph)
+			})
+			.transpose()
+	}
+
+	/// Parses a template source, substitutes its variables and deserializes
+	/// it against the schema.
+	fn render<T>(&self, source: &(String, Format), working_dir: &Path) -> Result<T, SetupError>
+	where
+		T: DeserializeOwned,
+	{
+		let (content, source_format) = source;
+
+		let value: Value = format::from_str(content, *source_format).map_err(|e| match e {
+			FormatError::Parse {
+				line,
+				column,
+				message,
+			} => SetupError::InvalidTemplate {
+				template: self.name.clone(),
+				message: format!("line {}, column {}: {}", line, column, message),
+			},
+			e => e.into(),
+		})?;
+
+		let vars = [
+			(
+				"{{home}}",
+				settings::get_home_dir().to_string_lossy().into_owned(),
+			),
+			("{{user}}", get_user_name()),
+			(
+				"{{working_dir}}",
+				working_dir.to_string_lossy().into_owned(),
+			),
+		];
+
+		serde_json::from_value(substitute(value, &vars)).map_err(|e| SetupError::InvalidTemplate {
+			template: self.name.clone(),
+			message: e.to_string(),
+		})
+	}
+}
+
+/// Reads a template file along with its format.
+fn read_source(path: &Path) -> Result<(String, Format), SetupError> {
+	Ok((fs::read_to_string(path)?, Format::from_path(path)))
+}
+
+/// Returns current user name, falling back to the home directory name.
+fn get_user_name() -> String {
+	env::var("USER")
+		.or_else(|_| env::var("USERNAME"))
+		.unwrap_or_else(|_| {
+			settings::get_home_dir()
+				.file_name()
+				.unwrap_or_default()
+				.to_string_lossy()
+				.into_owned()
+		})
+}
+
+/// Replaces template variables in every string of a value.
+fn substitute(value: Value, vars: &[(&str, String)]) -> Value {
+	match value {
+		Value::String(string) => {
+			Value::String(vars.iter().fold(string, |string, (var, replacement)| {
+				string.replace(var, replacement)
+			}))
+		}
+		Value::Array(values) => Value::Array(
+			values
+				.into_iter()
+				.map(|value| substitute(value, vars))
+				.collect(),
+		),
+		Value::Object(map) => Value::Object(
+			map.into_iter()
+				.map(|(key, value)| (key, substitute(value, vars)))
+				.collect(),
+		),
+		value => value,
+	}
+}
+
+/// Returns user templates directory.
+pub fn get_templates_dir() -> PathBuf {
+	settings::get_working_dir().join("templates")
+}
+
+/// Finds a template by path, user template name or bundled template name.
+pub fn load_template(template: &str) -> Result<Template, SetupError> {
+	let path = Path::new(template);
+
+	if path.is_dir() {
+		info!("Using template directory {}.", path.display());
+		return Template::from_dir(template, path);
+	}
+
+	if path.is_file() {
+		info!("Using template file {}.", path.display());
+		return Template::from_file(template, path);
+	}
+
+	let user_template = get_templates_dir().join(template);
+
+	if user_template.is_dir() {
+		info!("Using user template {}.", user_template.display());
+		return Template::from_dir(template, &user_template);
+	}
+
+	BUNDLED
+		.iter()
+		.find(|(name, _, _)| *name == template)
+		.map(|(name, settings_source, state_graph_source)| {
+			info!("Using bundled template {}.", name);
+
+			Template {
+				name: name.to_string(),
+				settings: Some((settings_source.to_string(), Format::Json)),
+				state_graph: Some((state_graph_source.to_string(), Format::Json)),
+			}
+		})
+		.ok_or_else(|| SetupError::TemplateNotFound {
+			template: template.to_string(),
+		})
+}
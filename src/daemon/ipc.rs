@@ -0,0 +1,231 @@
+//! Daemon IPC
+//!
+//! Typed requests and responses exchanged with the daemon as JSON lines over
+//! a Unix socket in the daemon directory. Each request line is answered by a
+//! single response line, except for subscriptions: once acknowledged, the
+//! daemon streams project events on that connection until it closes. The
+//! server loop stands in for the daemon where one isn't available.
+use crate::daemon::{self, Notification};
+use crate::errors::DaemonError;
+use crate::statehandler::StateGraph;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Daemon socket file name.
+pub const SOCKET_FILE: &str = "daemon.sock";
+
+/// Time given to the daemon to answer a request.
+pub const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Request sent to the daemon.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+	Ping,
+	StateGraph,
+	Notify { notification: Notification },
+	Subscribe,
+}
+
+/// Response sent by the daemon. State-graphs are boxed, as they outweigh the
+/// other responses.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum Response {
+	Pong { version: String },
+	StateGraph { state_graph: Box<StateGraph> },
+	Event { notification: Notification },
+	Ack,
+	Error { message: String },
+}
+
+/// Returns the daemon socket.
+pub fn get_socket_path() -> PathBuf {
+	daemon::get_daemon_dir().join(SOCKET_FILE)
+}
+
+/// Writes a message as a JSON line.
+fn send<T: Serialize>(mut stream: &UnixStream, message: &T) -> Result<(), DaemonError> {
+	let mut line = serde_json::to_string(message)?;
+	line.push('\n');
+	stream.write_all(line.as_bytes())?;
+	stream.flush()?;
+	Ok(())
+}
+
+/// Reads a response line. Fails once the connection closes.
+fn receive(reader: &mut BufReader<UnixStream>) -> Result<Response, DaemonError> {
+	let mut line = String::new();
+
+	if reader.read_line(&mut line)? == 0 {
+		return Err(DaemonError::Protocol {
+			message: String::from("the connection was closed"),
+		});
+	}
+
+	match serde_json::from_str(&line)? {
+		Response::Error { message } => Err(DaemonError::Remote { message: message }),
+		response => Ok(response),
+	}
+}
+
+/// Connection to the daemon.
+pub struct Client {
+	stream: UnixStream,
+	reader: BufReader<UnixStream>,
+}
+
+impl Client {
+	/// Connects to the daemon socket.
+	pub fn connect() -> Result<Self, DaemonError> {
+		Client::connect_to(&get_socket_path())
+	}
+
+	/// Connects to a daemon listening on some socket.
+	pub fn connect_to(socket_path: &Path) -> Result<Self, DaemonError> {
+		let stream = UnixStream::connect(socket_path)?;
+		stream.set_read_timeout(Some(TIMEOUT))?;
+		stream.set_write_timeout(Some(TIMEOUT))?;
+
+		Ok(Client {
+			reader: BufReader::new(stream.try_clone()?),
+			stream: stream,
+		})
+	}
+
+	/// Sends a request and waits for its response.
+	pub fn request(&mut self, request: &Request) -> Result<Response, DaemonError> {
+		send(&self.stream, request)?;
+		receive(&mut self.reader)
+	}
+
+	/// Subscribes to project events, turning the connection into a stream of
+	/// notifications.
+	pub fn subscribe(mut self) -> Result<Subscription, DaemonError> {
+		match self.request(&Request::Subscribe)? {
+			Response::Ack => {
+				self.stream.set_read_timeout(None)?;
+				Ok(Subscription {
+					reader: self.reader,
+				})
+			}
+			response => Err(unexpected(&response)),
+		}
+	}
+}
+
+/// Stream of project events sent by the daemon.
+pub struct Subscription {
+	reader: BufReader<UnixStream>,
+}
+
+impl Iterator for Subscription {
+	type Item = Result<Notification, DaemonError>;
+
+	/// Blocks until the next event. Ends once the daemon closes the stream.
+	fn next(&mut self) -> Option<Self::Item> {
+		match receive(&mut self.reader) {
+			Ok(Response::Event { notification }) => Some(Ok(notification)),
+			Ok(response) => Some(Err(unexpected(&response))),
+			Err(DaemonError::Protocol { .. }) => None,
+			Err(e) => Some(Err(e)),
+		}
+	}
+}
+
+/// Returns the error of a response not fitting its request.
+pub fn unexpected(response: &Response) -> DaemonError {
+	DaemonError::Protocol {
+		message: format!("unexpected response {:?}", response),
+	}
+}
+
+/// Sends a single request on a new connection.
+pub fn query(request: &Request) -> Result<Response, DaemonError> {
+	Client::connect()?.request(request)
+}
+
+/// Serves requests on a socket, answering them with a handler. Subscribers
+/// are sent the notifications received, once handled. Blocks for as long as
+/// the listener accepts connections.
+pub fn serve<F>(listener: UnixListener, handler: F)
+where
+	F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+	let handler = Arc::new(handler);
+	let subscribers: Arc<Mutex<Vec<UnixStream>>> = Arc::new(Mutex::new(Vec::new()));
+
+	for stream in listener.incoming() {
+		let stream = match stream {
+			Ok(stream) => stream,
+			Err(e) => {
+				warn!("Daemon connection failed: {}", e);
+				continue;
+			}
+		};
+
+		let handler = Arc::clone(&handler);
+		let subscribers = Arc::clone(&subscribers);
+
+		thread::spawn(move || {
+			if let Err(e) = handle(stream, &*handler, &subscribers) {
+				debug!("Daemon connection closed: {}", e);
+			}
+		});
+	}
+}
+
+/// Answers requests on a connection until it closes.
+fn handle<F>(
+	stream: UnixStream,
+	handler: &F,
+	subscribers: &Mutex<Vec<UnixStream>>,
+) -> Result<(), DaemonError>
+where
+	F: Fn(&Request) -> Response,
+{
+	let reader = BufReader::new(stream.try_clone()?);
+
+	for line in reader.lines() {
+		let request = match serde_json::from_str::<Request>(&line?) {
+			Ok(request) => request,
+			Err(e) => {
+				send(
+					&stream,
+					&Response::Error {
+						message: e.to_string(),
+					},
+				)?;
+				continue;
+			}
+		};
+
+		match request {
+			Request::Subscribe => {
+				if let Ok(mut subscribers) = subscribers.lock() {
+					subscribers.push(stream.try_clone()?);
+				}
+				send(&stream, &Response::Ack)?;
+			}
+			Request::Notify { ref notification } => {
+				let response = handler(&request);
+				if let Ok(mut subscribers) = subscribers.lock() {
+					let event = Response::Event {
+						notification: notification.clone(),
+					};
+					subscribers.retain(|subscriber| send(subscriber, &event).is_ok());
+				}
+				send(&stream, &response)?;
+			}
+			request => send(&stream, &handler(&request))?,
+		}
+	}
+
+	Ok(())
+}
@@ -0,0 +1,331 @@
+//! Daemon Client
+//!
+//! Controls the `godwit-daemon` process tracking project activity in the
+//! background. The daemon lives in the daemon directory (`GDD`), under the
+//! working directory unless set in the environment, holding its pid file, its
+//! log and its socket. Project events are sent over the socket, and dropped
+//! while the daemon isn't running. The daemon is a separate executable held to
+//! the contract in `docs/daemon.md`. Application tracing is dispatched through
+//! the `godwit-daemon` crate instead.
+#[cfg(unix)]
+pub mod ipc;
+
+use crate::env::Var;
+use crate::errors::DaemonError;
+use crate::glyph::Glyph;
+use crate::plugins::manage;
+use crate::settings;
+use crate::statehandler::{State, StateGraph};
+use chrono::{DateTime, Local};
+use getter_derive::Getter;
+use godwit_daemon::core::Ops;
+use godwit_daemon::dispatcher::{self, DispatchMsg};
+use godwit_daemon::prochandler::HandleOps;
+use godwit_daemon::runner::{self, Regress};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Daemon executable looked up on `PATH`.
+pub const DAEMON_EXEC: &str = "godwit-daemon";
+
+/// Daemon directory name within the working directory.
+pub const DAEMON_DIR: &str = "daemon";
+
+/// Pid file name.
+pub const PID_FILE: &str = "daemon.pid";
+
+/// Daemon log file name.
+pub const LOG_FILE: &str = "daemon.log";
+
+/// Time given to the daemon to stop.
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Daemon state as seen by the client.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+	Running { pid: u32 },
+	Stopped,
+}
+
+/// Project event the daemon is notified of.
+#[derive(Clone, Debug, Deserialize, Serialize, Getter)]
+#[serde(rename_all = "snake_case")]
+pub struct Notification {
+	event: String,
+	glyph: Glyph,
+	directory: Option<PathBuf>,
+	active: Option<Glyph>,
+	time: DateTime<Local>,
+}
+
+impl Notification {
+	/// Returns a notification of an event on a state.
+	pub fn new(event: &str, state: &State, active: Option<Glyph>) -> Self {
+		Notification {
+			event: event.to_string(),
+			glyph: state.get_glyph(),
+			directory: state.get_directory(),
+			active: active,
+			time: Local::now(),
+		}
+	}
+}
+
+/// Returns the daemon directory.
+pub fn get_daemon_dir() -> PathBuf {
+	env::var_os(Var::GDD.to_string())
+		.map(PathBuf::from)
+		.unwrap_or_else(|| settings::get_working_dir().join(DAEMON_DIR))
+}
+
+/// Returns the daemon log file.
+pub fn get_log_path() -> PathBuf {
+	get_daemon_dir().join(LOG_FILE)
+}
+
+/// Returns the pid file.
+fn get_pid_path() -> PathBuf {
+	get_daemon_dir().join(PID_FILE)
+}
+
+/// Checks whether a process is alive.
+#[cfg(unix)]
+fn is_alive(pid: u32) -> bool {
+	unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+/// Checks whether a process is alive.
+#[cfg(not(unix))]
+fn is_alive(_pid: u32) -> bool {
+	false
+}
+
+/// Returns daemon status, clearing a stale pid file.
+pub fn status() -> Result<Status, DaemonError> {
+	let pid_path = get_pid_path();
+
+	let pid = match fs::read_to_string(&pid_path) {
+		Ok(pid) => pid.trim().parse::<u32>().ok(),
+		Err(_) => return Ok(Status::Stopped),
+	};
+
+	match pid {
+		Some(pid) if is_alive(pid) => Ok(Status::Running { pid: pid }),
+		_ => {
+			debug!("Removing stale pid file {}.", pid_path.display());
+			fs::remove_file(pid_path)?;
+			Ok(Status::Stopped)
+		}
+	}
+}
+
+/// Starts the daemon detached from the terminal. Returns its pid.
+pub fn start() -> Result<u32, DaemonError> {
+	if let Status::Running { pid } = status()? {
+		return Err(DaemonError::AlreadyRunning { pid: pid });
+	}
+
+	let exec = manage::find_executable(DAEMON_EXEC).ok_or_else(|| DaemonError::NotFound {
+		exec: DAEMON_EXEC.to_string(),
+	})?;
+
+	let daemon_dir = get_daemon_dir();
+	fs::create_dir_all(&daemon_dir)?;
+
+	let log = OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(get_log_path())?;
+
+	let mut command = Command::new(exec);
+	command
+		.current_dir(&daemon_dir)
+		.env(Var::GDD.to_string(), &daemon_dir)
+		.env(Var::GWD.to_string(), settings::get_working_dir())
+		.stdin(Stdio::null())
+		.stdout(log.try_clone()?)
+		.stderr(log);
+
+	#[cfg(unix)]
+	unsafe {
+		use std::os::unix::process::CommandExt;
+
+		command.pre_exec(|| {
+			libc::setsid();
+			Ok(())
+		});
+	}
+
+	let pid = command.spawn()?.id();
+	fs::write(get_pid_path(), pid.to_string())?;
+
+	Ok(pid)
+}
+
+/// Stops the daemon, waiting for it to exit.
+pub fn stop() -> Result<(), DaemonError> {
+	let pid = match status()? {
+		Status::Running { pid } => pid,
+		Status::Stopped => return Err(DaemonError::NotRunning),
+	};
+
+	#[cfg(unix)]
+	unsafe {
+		libc::kill(pid as libc::pid_t, libc::SIGTERM);
+	}
+
+	let deadline = Instant::now() + STOP_TIMEOUT;
+
+	while is_alive(pid) {
+		if Instant::now() >= deadline {
+			return Err(DaemonError::StopTimeout { pid: pid });
+		}
+		thread::sleep(Duration::from_millis(100));
+	}
+
+	fs::remove_file(get_pid_path())?;
+
+	#[cfg(unix)]
+	{
+		let _ = fs::remove_file(ipc::get_socket_path());
+	}
+
+	Ok(())
+}
+
+/// Returns the last lines of the daemon log.
+pub fn logs(lines: usize) -> Result<Vec<String>, DaemonError> {
+	let content = match fs::read(get_log_path()) {
+		Ok(content) => content,
+		Err(_) => return Ok(Vec::new()),
+	};
+
+	let content = String::from_utf8_lossy(&content);
+	let log_lines = content.lines().collect::<Vec<_>>();
+
+	Ok(log_lines[log_lines.len().saturating_sub(lines)..]
+		.iter()
+		.map(|line| line.to_string())
+		.collect())
+}
+
+/// Notifies the daemon of a project event. Does nothing unless it runs.
+pub fn notify(notification: &Notification) -> Result<(), DaemonError> {
+	if settings::get_settings()?.get_headless() {
+		return Ok(());
+	}
+
+	if status()? == Status::Stopped {
+		debug!(
+			"Daemon isn't running, dropping {} notification.",
+			notification.event
+		);
+		return Ok(());
+	}
+
+	send_notification(notification)
+}
+
+/// Asks the daemon to trace the state of an application through its backend,
+/// discarding what was traced before when refreshed.
+pub fn trace(application: &str, refresh: bool) -> Result<(), DaemonError> {
+	if !runner::daemon_running() {
+		return Err(DaemonError::NotRunning);
+	}
+
+	let response = dispatcher::send(DispatchMsg {
+		proctype: HandleOps::Run,
+		func: Some(Ops::Trace),
+		application: Some(application.to_lowercase()),
+		refresh: refresh,
+		regress_counter: Some(Regress::Once),
+	})?;
+
+	// Success codes start with `S`, failures with `E`.
+	if response.code.starts_with('S') {
+		Ok(())
+	} else {
+		Err(DaemonError::Remote {
+			message: response.message,
+		})
+	}
+}
+
+/// Returns the state-graph held by the daemon, unless it isn't running or
+/// fails to answer.
+#[cfg(unix)]
+pub fn state_graph() -> Option<StateGraph> {
+	if status().ok()? == Status::Stopped {
+		return None;
+	}
+
+	match ipc::query(&ipc::Request::StateGraph) {
+		Ok(ipc::Response::StateGraph { state_graph }) => Some(*state_graph),
+		Ok(response) => {
+			debug!("{}", ipc::unexpected(&response));
+			None
+		}
+		Err(e) => {
+			debug!("Daemon didn't answer with the state-graph: {}", e);
+			None
+		}
+	}
+}
+
+/// Returns the state-graph held by the daemon.
+#[cfg(not(unix))]
+pub fn state_graph() -> Option<StateGraph> {
+	None
+}
+
+/// Passes project events the daemon is notified of to a callback, until the
+/// daemon closes the stream.
+#[cfg(unix)]
+pub fn watch<F>(mut on_event: F) -> Result<(), DaemonError>
+where
+	F: FnMut(Notification),
+{
+	if status()? == Status::Stopped {
+		return Err(DaemonError::NotRunning);
+	}
+
+	for notification in ipc::Client::connect()?.subscribe()? {
+		on_event(notification?);
+	}
+
+	Ok(())
+}
+
+/// Passes project events the daemon is notified of to a callback.
+#[cfg(not(unix))]
+pub fn watch<F>(_on_event: F) -> Result<(), DaemonError>
+where
+	F: FnMut(Notification),
+{
+	Err(DaemonError::NotRunning)
+}
+
+/// Sends a notification over the daemon socket.
+#[cfg(unix)]
+fn send_notification(notification: &Notification) -> Result<(), DaemonError> {
+	let request = ipc::Request::Notify {
+		notification: notification.clone(),
+	};
+
+	match ipc::query(&request)? {
+		ipc::Response::Ack => Ok(()),
+		response => Err(ipc::unexpected(&response)),
+	}
+}
+
+/// Sends a notification over the daemon socket.
+#[cfg(not(unix))]
+fn send_notification(_notification: &Notification) -> Result<(), DaemonError> {
+	Err(DaemonError::NotRunning)
+}
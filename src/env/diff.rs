@@ -0,0 +1,133 @@
+//! Line Diff
+//!
+//! Minimal unified diff renderer used to preview edits on shell configuration
+//! files. Lines are compared as raw bytes so that non-UTF-8 content survives.
+
+/// Number of unchanged lines kept around each change.
+const CONTEXT: usize = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Edit {
+	Keep(usize, usize),
+	Remove(usize),
+	Add(usize),
+}
+
+/// Computes the shortest edit script between two line sets.
+fn edit_script(old: &[Vec<u8>], new: &[Vec<u8>]) -> Vec<Edit> {
+	let (n, m) = (old.len(), new.len());
+	let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+
+	for i in (0..n).rev() {
+		for j in (0..m).rev() {
+			lcs[i][j] = if old[i] == new[j] {
+				lcs[i + 1][j + 1] + 1
+			} else {
+				lcs[i + 1][j].max(lcs[i][j + 1])
+			};
+		}
+	}
+
+	let (mut i, mut j) = (0, 0);
+	let mut script = Vec::new();
+
+	while i < n && j < m {
+		if old[i] == new[j] {
+			script.push(Edit::Keep(i, j));
+			i += 1;
+			j += 1;
+		} else if lcs[i + 1][j] >= lcs[i][j + 1] {
+			script.push(Edit::Remove(i));
+			i += 1;
+		} else {
+			script.push(Edit::Add(j));
+			j += 1;
+		}
+	}
+
+	script.extend((i..n).map(Edit::Remove));
+	script.extend((j..m).map(Edit::Add));
+	script
+}
+
+/// Formats hunk range as expected by unified diff headers.
+fn hunk_range(start: usize, count: usize) -> String {
+	if count == 0 {
+		format!("{},0", start)
+	} else {
+		format!("{},{}", start + 1, count)
+	}
+}
+
+/// Renders a unified diff between two line sets. Returns an empty string on
+/// identical inputs.
+pub fn unified_diff(old_name: &str, new_name: &str, old: &[Vec<u8>], new: &[Vec<u8>]) -> String {
+	let script = edit_script(old, new);
+
+	let changes = script
+		.iter()
+		.enumerate()
+		.filter(|(_, edit)| !matches!(edit, Edit::Keep(..)))
+		.map(|(idx, _)| idx)
+		.collect::<Vec<_>>();
+
+	if changes.is_empty() {
+		return String::new();
+	}
+
+	// Group change indices into hunks sharing their context.
+	let mut hunks: Vec<(usize, usize)> = Vec::new();
+
+	for idx in changes {
+		let start = idx.saturating_sub(CONTEXT);
+		let end = (idx + CONTEXT + 1).min(script.len());
+
+		match hunks.last_mut() {
+			Some(last) if start <= last.1 => last.1 = end,
+			_ => hunks.push((start, end)),
+		}
+	}
+
+	let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+
+	for (start, end) in hunks {
+		let (old_start, new_start) =
+			script[..start]
+				.iter()
+				.fold((0, 0), |(o, n), edit| match edit {
+					Edit::Keep(..) => (o + 1, n + 1),
+					Edit::Remove(_) => (o + 1, n),
+					Edit::Add(_) => (o, n + 1),
+				});
+
+		let hunk = &script[start..end];
+
+		let old_count = hunk
+			.iter()
+			.filter(|edit| !matches!(edit, Edit::Add(_)))
+			.count();
+		let new_count = hunk
+			.iter()
+			.filter(|edit| !matches!(edit, Edit::Remove(_)))
+			.count();
+
+		out.push_str(&format!(
+			"@@ -{} +{} @@\n",
+			hunk_range(old_start, old_count),
+			hunk_range(new_start, new_count)
+		));
+
+		for edit in hunk {
+			let (marker, line) = match *edit {
+				Edit::Keep(i, _) => (' ', &old[i]),
+				Edit::Remove(i) => ('-', &old[i]),
+				Edit::Add(j) => ('+', &new[j]),
+			};
+			out.push(marker);
+			out.push_str(&String::from_utf8_lossy(line));
+			out.push('\n');
+		}
+	}
+
+	out
+}
@@ -1,14 +1,37 @@
 //! Environment Manipulation Utility
 //!
 //! A utility abstraction over Godwit environment variables.
+mod diff;
+
 use crate::errors::EnvError;
 use dirs;
+use glob::glob;
+use log::info;
 use std::fmt::Display;
-use std::fs::File;
-use std::io::{prelude::*, BufReader};
+use std::fs;
 use std::iter::IntoIterator;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Extension marker for shell configuration backups.
+const BACKUP_EXT: &str = "gwbak";
+
+/// Opening marker of the variables Godwit keeps in a configuration file.
+const BLOCK_START: &str = "# >>> Added by Godwit >>>";
+
+/// Closing marker of the variables Godwit keeps in a configuration file.
+const BLOCK_END: &str = "# <<< Added by Godwit <<<";
+
+/// Preview mode for configuration edits. Edits print a diff instead of
+/// being written when set.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Export mode for headless usage. Variables are printed as shell commands
+/// instead of being written to shell configuration files when set, and
+/// stdout carries nothing else.
+static EXPORT_ONLY: AtomicBool = AtomicBool::new(false);
 
 #[derive(Copy, Clone, Debug)]
 pub enum Shell {
@@ -85,92 +108,424 @@ pub fn get_cfg_paths(shell: Shell) -> Vec<PathBuf> {
 	}
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Var {
-	GWD, // Working directory
-	GSD, // States directory
-	GDD, // Daemon directory
-	GPD, // Project directory
+	GWD,            // Working directory
+	GSD,            // States directory
+	GDD,            // Daemon directory
+	GPD,            // Project directory
+	Custom(String), // User-defined variable
 }
 
 impl Var {
 	pub fn to_string(&self) -> &str {
-		match *self {
+		match self {
 			Var::GWD => "GWD",
 			Var::GSD => "GSD",
 			Var::GDD => "GDD",
 			Var::GPD => "GPD",
+			Var::Custom(name) => name,
 		}
 	}
 	pub fn iterator() -> impl Iterator<Item = Var> {
-		[Var::GWD, Var::GSD, Var::GDD, Var::GPD].iter().copied()
+		[Var::GWD, Var::GSD, Var::GDD, Var::GPD].iter().cloned()
 	}
 }
 
-pub fn replace_copy_env<E, LB, P>(env_var: E, replace_str: LB, file_path: P) -> Result<(), EnvError>
+/// Toggles preview mode for configuration edits.
+pub fn set_dry_run(dry_run: bool) {
+	DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+/// Toggles export mode for environment variables.
+pub fn set_export_only(export_only: bool) {
+	EXPORT_ONLY.store(export_only, Ordering::Relaxed);
+}
+
+/// Checks whether stdout is kept for shell commands.
+pub fn is_export_only() -> bool {
+	EXPORT_ONLY.load(Ordering::Relaxed)
+}
+
+/// Quotes a value for POSIX shells.
+fn shell_quote(value: &str) -> String {
+	format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Quotes a value for fish.
+fn fish_quote(value: &str) -> String {
+	format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Splits raw file content into lines without requiring valid UTF-8.
+fn split_lines(content: &[u8]) -> Vec<Vec<u8>> {
+	let content = content.strip_suffix(b"\n").unwrap_or(content);
+
+	if content.is_empty() {
+		return Vec::new();
+	}
+
+	content
+		.split(|&byte| byte == b'\n')
+		.map(Vec::from)
+		.collect()
+}
+
+/// Joins lines back into newline-terminated file content.
+fn join_lines(lines: &[Vec<u8>]) -> Vec<u8> {
+	lines.iter().fold(Vec::new(), |mut content, line| {
+		content.extend_from_slice(line);
+		content.push(b'\n');
+		content
+	})
+}
+
+/// Returns the backup path of a configuration file for given timestamp.
+fn backup_path<P>(file_path: P, timestamp: u64) -> PathBuf
 where
-	E: Display,
-	LB: AsRef<[u8]> + Display,
 	P: AsRef<Path>,
 {
-	let file = File::open(&file_path)?;
+	let mut backup_name = file_path
+		.as_ref()
+		.file_name()
+		.unwrap_or_default()
+		.to_os_string();
 
-	let reader = BufReader::new(&file);
+	backup_name.push(format!(".{}.{}", BACKUP_EXT, timestamp));
+	file_path.as_ref().with_file_name(backup_name)
+}
 
-	let mut env_found = false;
+/// Creates a timestamped backup of a configuration file. Multiple edits in
+/// the same second share the earliest backup.
+pub fn backup_file<P>(file_path: P) -> Result<PathBuf, EnvError>
+where
+	P: AsRef<Path>,
+{
+	let timestamp = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|elapsed| elapsed.as_secs())
+		.unwrap_or_default();
 
-	let mut lines = reader
-		.lines()
-		.map(|line| {
-			let line = line.expect("Line is unreadable.");
-			if line.starts_with(&format!("{}=", env_var)) {
-				env_found = true;
-				replace_str.to_string()
-			} else {
-				line
-			}
+	let backup = backup_path(&file_path, timestamp);
+
+	if !backup.exists() {
+		fs::copy(&file_path, &backup)?;
+		info!(
+			"Backed up {} to {}",
+			file_path.as_ref().display(),
+			backup.display()
+		);
+	}
+
+	Ok(backup)
+}
+
+/// Lists backups of a configuration file, oldest first.
+pub fn list_backups<P>(file_path: P) -> Result<Vec<PathBuf>, EnvError>
+where
+	P: AsRef<Path>,
+{
+	let pattern = format!(
+		"{}.{}.*",
+		glob::Pattern::escape(&file_path.as_ref().to_string_lossy()),
+		BACKUP_EXT
+	);
+
+	let mut backups = glob(&pattern)?
+		.filter_map(Result::ok)
+		.filter_map(|backup| {
+			backup
+				.extension()
+				.and_then(|ext| ext.to_str())
+				.and_then(|ext| ext.parse::<u64>().ok())
+				.map(|timestamp| (timestamp, backup.clone()))
 		})
 		.collect::<Vec<_>>();
 
-	if !env_found {
-		lines.push("\n".to_string());
-		lines.push(String::from("# Added by Godwit."));
-		lines.push(replace_str.to_string());
-	}
+	backups.sort();
 
-	let mut file = File::create(&file_path)?;
+	Ok(backups.into_iter().map(|(_, backup)| backup).collect())
+}
 
-	for line in lines {
-		file.write_all(line.as_bytes())?;
+/// Prints the diff of an edit to a configuration file.
+fn print_diff<P>(file_path: P, original: &[u8], modified: &[u8])
+where
+	P: AsRef<Path>,
+{
+	let name = file_path.as_ref().display().to_string();
+	print!(
+		"{}",
+		diff::unified_diff(&name, &name, &split_lines(original), &split_lines(modified))
+	);
+}
 
-		file.write_all(b"\n")?;
+/// Writes edited content to a configuration file, or prints the diff in
+/// preview mode. Backs up the original before overwriting.
+fn commit_edit<P>(file_path: P, original: &[u8], modified: &[u8]) -> Result<(), EnvError>
+where
+	P: AsRef<Path>,
+{
+	if original == modified {
+		return Ok(());
 	}
 
+	if DRY_RUN.load(Ordering::Relaxed) {
+		print_diff(&file_path, original, modified);
+		return Ok(());
+	}
+
+	if file_path.as_ref().exists() {
+		backup_file(&file_path)?;
+	}
+
+	fs::write(&file_path, modified)?;
+
 	Ok(())
 }
 
-pub fn set_env_var<EV>(var: Var, value: EV) -> Result<(), EnvError>
+/// Restores a configuration file from its latest backup, or prints the diff
+/// in preview mode. The file is overwritten as is, without being backed up,
+/// and backups are kept.
+pub fn restore_backup<P>(file_path: P) -> Result<PathBuf, EnvError>
 where
-	EV: Display + Default,
+	P: AsRef<Path>,
 {
-	if cfg!(target_os = "windows") {
-		Command::new("setx")
-			.arg(var.to_string())
-			.arg(value.to_string())
-			.output()?;
-	} else {
-		let env_line = format!("{}=\"{}\"", var.to_string(), value);
+	let backup = list_backups(&file_path)?
+		.pop()
+		.ok_or_else(|| EnvError::BackupNotFound {
+			file: file_path.as_ref().to_string_lossy().into_owned(),
+		})?;
+
+	if DRY_RUN.load(Ordering::Relaxed) {
+		let original = fs::read(&file_path).unwrap_or_default();
+		let restored = fs::read(&backup)?;
 
-		for shell in Shell::iterator() {
-			for cfg_path in get_cfg_paths(shell) {
-				replace_copy_env(var.to_string(), &env_line, cfg_path)?;
+		if original != restored {
+			print_diff(&file_path, &original, &restored);
+		}
+		return Ok(backup);
+	}
+
+	fs::copy(&backup, &file_path)?;
+
+	Ok(backup)
+}
+
+/// Restores every shell configuration file edited by Godwit.
+pub fn restore_cfg_backups() -> Result<Vec<PathBuf>, EnvError> {
+	let mut cfg_paths = Shell::iterator()
+		.flat_map(get_cfg_paths)
+		.collect::<Vec<_>>();
+
+	cfg_paths.sort();
+	cfg_paths.dedup();
+
+	let mut restored = Vec::new();
+
+	for cfg_path in cfg_paths {
+		if !list_backups(&cfg_path)?.is_empty() {
+			restore_backup(&cfg_path)?;
+			restored.push(cfg_path);
+		}
+	}
+
+	Ok(restored)
+}
+
+/// Returns the line setting a variable in a shell configuration file.
+fn env_line(shell: Shell, var: &str, value: &str) -> String {
+	match shell {
+		Shell::FISH => format!("set -gx {} {}", var, fish_quote(value)),
+		Shell::CSH | Shell::TCSH => format!("setenv {} {}", var, shell_quote(value)),
+		Shell::BASH | Shell::ZSH | Shell::KSH => format!("export {}={}", var, shell_quote(value)),
+	}
+}
+
+/// Checks whether a line sets a variable, in any shell syntax.
+fn declares(line: &[u8], var: &str) -> bool {
+	[
+		format!("export {}=", var),
+		format!("set -gx {} ", var),
+		format!("setenv {} ", var),
+	]
+	.iter()
+	.any(|prefix| line.starts_with(prefix.as_bytes()))
+}
+
+/// Returns the start and end marker lines of the Godwit block.
+fn block_bounds(lines: &[Vec<u8>]) -> Option<(usize, usize)> {
+	let start = lines
+		.iter()
+		.position(|line| line == BLOCK_START.as_bytes())?;
+	let end = lines[start..]
+		.iter()
+		.position(|line| line == BLOCK_END.as_bytes())?;
+
+	Some((start, start + end))
+}
+
+/// Sets a variable line within the Godwit block of configuration lines,
+/// adding the block when missing.
+fn set_block_line(lines: &mut Vec<Vec<u8>>, env_var: &str, line: &[u8]) {
+	match block_bounds(lines) {
+		Some((start, end)) => {
+			match (start + 1..end).find(|&index| declares(&lines[index], env_var)) {
+				Some(index) => lines[index] = line.to_vec(),
+				None => lines.insert(end, line.to_vec()),
+			}
+		}
+		None => {
+			if lines.last().map_or(false, |last| !last.is_empty()) {
+				lines.push(Vec::new());
+			}
+			lines.push(BLOCK_START.as_bytes().to_vec());
+			lines.push(line.to_vec());
+			lines.push(BLOCK_END.as_bytes().to_vec());
+		}
+	}
+}
+
+/// Drops a variable line from the Godwit block of configuration lines,
+/// dropping the block once empty. Lines outside of it are left alone.
+fn drop_block_line(lines: &mut Vec<Vec<u8>>, env_var: &str) {
+	let (start, end) = match block_bounds(lines) {
+		Some(bounds) => bounds,
+		None => return,
+	};
+
+	let tail = lines.split_off(end + 1);
+	let block = lines
+		.split_off(start)
+		.into_iter()
+		.filter(|line| !declares(line, env_var))
+		.collect::<Vec<_>>();
+
+	if block.len() > 2 {
+		lines.extend(block);
+	} else if lines.last().map_or(false, |last| last.is_empty()) {
+		lines.pop();
+	}
+	lines.extend(tail);
+}
+
+/// Sets a variable line within the Godwit block of a configuration file,
+/// adding the block when missing.
+pub fn replace_copy_env<E, LB, P>(env_var: E, replace_str: LB, file_path: P) -> Result<(), EnvError>
+where
+	E: Display,
+	LB: AsRef<[u8]> + Display,
+	P: AsRef<Path>,
+{
+	let original = fs::read(&file_path)?;
+	let mut lines = split_lines(&original);
+
+	set_block_line(&mut lines, &env_var.to_string(), replace_str.as_ref());
+
+	commit_edit(&file_path, &original, &join_lines(&lines))
+}
+
+/// Drops a variable line from the Godwit block of a configuration file,
+/// dropping the block once empty. Lines outside of it are left alone.
+pub fn remove_copy_env<E, P>(env_var: E, file_path: P) -> Result<(), EnvError>
+where
+	E: Display,
+	P: AsRef<Path>,
+{
+	let original = fs::read(&file_path)?;
+	let mut lines = split_lines(&original);
+
+	drop_block_line(&mut lines, &env_var.to_string());
+
+	commit_edit(&file_path, &original, &join_lines(&lines))
+}
+
+/// Sets and unsets variables within the Godwit block of a configuration file
+/// in a single edit.
+fn edit_copy_env<P>(
+	shell: Shell,
+	vars: &[(Var, Option<String>)],
+	file_path: P,
+) -> Result<(), EnvError>
+where
+	P: AsRef<Path>,
+{
+	let original = fs::read(&file_path)?;
+	let mut lines = split_lines(&original);
+
+	for (var, value) in vars {
+		match value {
+			Some(value) => set_block_line(
+				&mut lines,
+				var.to_string(),
+				env_line(shell, var.to_string(), value).as_bytes(),
+			),
+			None => drop_block_line(&mut lines, var.to_string()),
+		}
+	}
+
+	commit_edit(&file_path, &original, &join_lines(&lines))
+}
+
+/// Returns every shell configuration file along with the shell whose syntax
+/// it is written in. Files shared by shells go to the last of them.
+fn get_all_cfg_paths() -> Vec<(PathBuf, Shell)> {
+	let mut cfg_paths: Vec<(PathBuf, Shell)> = Vec::new();
+
+	for shell in Shell::iterator() {
+		for cfg_path in get_cfg_paths(shell) {
+			match cfg_paths.iter_mut().find(|(path, _)| *path == cfg_path) {
+				Some(entry) => entry.1 = shell,
+				None => cfg_paths.push((cfg_path, shell)),
 			}
 		}
 	}
 
+	cfg_paths
+}
+
+/// Sets variables given a value and unsets the others, editing every shell
+/// configuration file once.
+pub fn set_env_vars(vars: &[(Var, Option<String>)]) -> Result<(), EnvError> {
+	if vars.is_empty() {
+		return Ok(());
+	}
+
+	if is_export_only() {
+		for (var, value) in vars {
+			match value {
+				Some(value) => println!("export {}={}", var.to_string(), shell_quote(value)),
+				None => println!("unset {}", var.to_string()),
+			}
+		}
+	} else if cfg!(target_os = "windows") {
+		for (var, value) in vars {
+			match value {
+				Some(value) => Command::new("setx")
+					.arg(var.to_string())
+					.arg(value)
+					.output()?,
+				None => Command::new("reg")
+					.args(["delete", "HKCU\\Environment", "/f", "/v"])
+					.arg(var.to_string())
+					.output()?,
+			};
+		}
+	} else {
+		for (cfg_path, shell) in get_all_cfg_paths() {
+			edit_copy_env(shell, vars, cfg_path)?;
+		}
+	}
+
 	Ok(())
 }
 
+pub fn set_env_var<EV>(var: Var, value: EV) -> Result<(), EnvError>
+where
+	EV: Display + Default,
+{
+	set_env_vars(&[(var, Some(value.to_string()))])
+}
+
 pub fn set_env_shell_var<EV>(shell: Shell, var: Var, value: EV) -> Result<(), EnvError>
 where
 	EV: Display + Default,
@@ -181,7 +536,7 @@ where
 			.arg(value.to_string())
 			.output()?;
 	} else {
-		let env_line = format!("{}=\"{}\"", var.to_string(), value);
+		let env_line = env_line(shell, var.to_string(), &value.to_string());
 
 		for cfg_path in get_cfg_paths(shell) {
 			replace_copy_env(var.to_string(), &env_line, cfg_path)?;
@@ -190,3 +545,7 @@ where
 
 	Ok(())
 }
+
+pub fn unset_env_var(var: Var) -> Result<(), EnvError> {
+	set_env_vars(&[(var, None)])
+}
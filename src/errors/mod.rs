@@ -21,7 +21,10 @@ custom_error! {pub GlyphError
 }
 
 custom_error! {pub EnvError
+	BackupNotFound{file: String} = "No backup was found for {file}.",
+
 	IO {source: std::io::Error} = "A resource IO error occured while operating environment variables.",
+	Pattern {source: glob::PatternError} = "Glob pattern matching threw an error.",
 }
 
 custom_error! {pub TuiError
@@ -30,18 +33,36 @@ custom_error! {pub TuiError
 	MpscRecv {source: std::sync::mpsc::RecvError} = "Unexpected Mpsc reveive error occured.",
 }
 
+custom_error! {pub FormatError
+	UnknownFormat{format: String} = "The format {format} isn't supported.",
+	Parse{line: usize, column: usize, message: String} = "Parsing failed at line {line}, column {column}: {message}.",
+
+	IO {source: std::io::Error} = "IO operations failed unexpectedly.",
+	Json {source: serde_json::Error} = "JSON serialization threw an error.",
+	Toml {source: toml::ser::Error} = "TOML serialization threw an error.",
+	TomlEdit {source: toml_edit::TomlError} = "TOML rewriting threw an error.",
+	Yaml {source: serde_yaml::Error} = "YAML serialization threw an error.",
+}
+
 custom_error! {pub SettingsError
 	WorkingDirNotFound = "The Godwit working directory was not found.",
 	SettingsNotFound{file: String} = "The settings {file} was not found.",
+	BrokenSymlink{link: String, target: String} = "The working directory link {link} points to missing {target}.",
+	WorkingDirOccupied{path: String} = "The path {path} exists and isn't a working directory link.",
+	PurgeRefused{path: String} = "The directory {path} holds no Godwit settings and won't be purged.",
 
 	SettingsAlreadyExists{mode: SettingsMode} = "The settings of type {mode} already exist.",
 
-	InvalidSettings{file: String} = "The settings {file} seems to be invalid.",
+	InvalidSettings{file: String, line: usize, column: usize, message: String} = "The settings {file} seems to be invalid at line {line}, column {column}: {message}.",
+	InvalidEffectiveSettings{message: String} = "The effective settings seem to be invalid: {message}.",
+	UnknownKey{key: String} = "The settings key {key} doesn't exist.",
 
 	DisallowedUpsert = "Disallowd because upsert option wasn't passed.",
 	DisallowedHeadless = "Disallowed because the operation isn't permitted on headless usage.",
 
 	IO {source: std::io::Error} = "IO operations failed unexpectedly.",
+	Json {source: serde_json::Error} = "Settings couldn't be serialized.",
+	Format {source: FormatError} = "Settings couldn't be read or written. {source}",
 	Pattern {source: glob::PatternError} = "Glob pattern matching threw an error.",
 }
 
@@ -52,12 +73,16 @@ custom_error! {pub StateError
 	StateGraphAlreadyExists = "A state-graph already exists.",
 	StateAlreadyExists{state: String} = "The state {state} already exists.",
 
-	InvalidStateGraph{file: String} = "The state-graph {file} seems to be invalid.",
+	InvalidStateGraph{file: String, line: usize, column: usize, message: String} = "The state-graph {file} seems to be invalid at line {line}, column {column}: {message}.",
+	InvalidEmbeddedStateGraph{file: String, message: String} = "The state-graph embedded in {file} seems to be invalid: {message}.",
 	InvalidState{state: String} = "The state {state} seems to be invalid.",
 
 	EmptyStateList = "No states were found.",
+	NoActiveState = "No active state is set.",
 
 	IO {source: std::io::Error} = "IO operations failed unexpectedly.",
+	Format {source: FormatError} = "The state-graph couldn't be read or written. {source}",
+	Json {source: serde_json::Error} = "The state-graph couldn't be serialized.",
 	Settings {source: SettingsError} = "Unexpected error thrown in Settings module.",
 	Env {source: EnvError} = "Unexpected error thrown in Env module.",
 }
@@ -70,21 +95,127 @@ custom_error! {pub IOError
 custom_error! {pub PluginError
 	StdErr {message: String} = "Child detached process returned error {message}.",
 	PluginNotFound {plugin: String} = "The plugin {plugin} was not found.",
+	PluginDisabled {plugin: String} = "The plugin {plugin} is disabled.",
+	PluginExists {plugin: String} = "A plugin named {plugin} is already configured.",
+	MalformedExec {plugin: String, exec: String, message: String} = "The exec `{exec}` of plugin {plugin} is malformed: {message}.",
+	Timeout {plugin: String, seconds: u64} = "The plugin {plugin} timed out after {seconds} seconds.",
+	Interrupted {plugin: String} = "The plugin {plugin} was interrupted.",
+	HookFailed {plugin: String, hook: String, message: String} = "The plugin {plugin} failed on {hook}: {message}.",
+	UnknownHook {hook: String} = "The hook {hook} doesn't exist.",
+	Protocol {plugin: String, message: String} = "The plugin {plugin} broke protocol: {message}.",
+	ProtocolMismatch {plugin: String, expected: u32, found: u32} = "The plugin {plugin} speaks protocol version {found} instead of {expected}.",
+	Unsupported {plugin: String, method: String} = "The plugin {plugin} doesn't support {method}.",
+	Remote {plugin: String, code: String, message: String} = "The plugin {plugin} returned error {code}: {message}.",
 
 	IO {source: std::io::Error} = "IO operations failed unexpectedly.",
 	FromUtf8 {source: std::string::FromUtf8Error} = "UTF-8 conversion threw an error.",
+	Json {source: serde_json::Error} = "Plugin messages couldn't be serialized.",
+	Scaffold {source: ScaffoldError} = "Scaffolding failed: {source}",
 
 	Settings {source: SettingsError} = "Setup failed due to error thrown by Settings module.",
 }
 
+custom_error! {pub HookError
+	Failed {hook: String, action: String, status: String} = "The {hook} hook {action} failed with {status}.",
+
+	IO {source: std::io::Error} = "IO operations failed unexpectedly.",
+	Plugin {source: PluginError} = "Plugin failed: {source}",
+	Settings {source: SettingsError} = "Unexpected error thrown in Settings module.",
+}
+
+custom_error! {pub ScaffoldError
+	TemplateNotFound{template: String} = "The scaffold template {template} was not found.",
+	InvalidManifest{template: String, message: String} = "The manifest of scaffold template {template} seems to be invalid: {message}.",
+	TargetNotEmpty{path: String} = "The scaffolding target {path} isn't empty.",
+	InvalidName{name: String, substituted: String} = "The template file {name} would be named {substituted}, which isn't a plain file name.",
+
+	IO {source: std::io::Error} = "IO operations failed unexpectedly.",
+	IOHandler {source: IOError} = "Prompting for template values failed.",
+}
+
+custom_error! {pub DaemonError
+	NotFound{exec: String} = "The daemon executable {exec} was not found on PATH.",
+	AlreadyRunning{pid: u32} = "The daemon is already running with pid {pid}.",
+	NotRunning = "The daemon isn't running.",
+	StopTimeout{pid: u32} = "The daemon with pid {pid} didn't stop in time.",
+	Protocol{message: String} = "The daemon broke protocol: {message}.",
+	Remote{message: String} = "The daemon failed to answer: {message}.",
+
+	IO {source: std::io::Error} = "IO operations failed unexpectedly.",
+	Json {source: serde_json::Error} = "Daemon messages couldn't be serialized or parsed.",
+	Settings {source: SettingsError} = "Unexpected error thrown in Settings module.",
+	Dispatch {source: godwit_daemon::errors::NetworkError} = "The daemon couldn't be reached to trace an application.",
+}
+
+custom_error! {pub ActivityError
+	Watch {source: notify::Error} = "Project directories couldn't be watched.",
+
+	IO {source: std::io::Error} = "IO operations failed unexpectedly.",
+	Json {source: serde_json::Error} = "Recorded changes couldn't be serialized or parsed.",
+	Settings {source: SettingsError} = "Unexpected error thrown in Settings module.",
+	State {source: StateError} = "Unexpected error thrown in State module.",
+}
+
+custom_error! {pub HistoryError
+	InvalidRange{range: String} = "The range {range} is invalid. Use <from>..<to>, either end being optional.",
+	RangeOutOfBounds{range: String, len: usize} = "The range {range} doesn't fit the {len} history entries.",
+
+	Activity {source: ActivityError} = "{source}",
+	Settings {source: SettingsError} = "Unexpected error thrown in Settings module.",
+}
+
+custom_error! {pub TrackerError
+	UnknownPeriod{period: String} = "The period {period} is unknown. Use day or week.",
+
+	IO {source: std::io::Error} = "IO operations failed unexpectedly.",
+	Json {source: serde_json::Error} = "Tracked sessions couldn't be serialized or parsed.",
+	Settings {source: SettingsError} = "Unexpected error thrown in Settings module.",
+}
+
+custom_error! {pub SecretError
+	SecretNotFound{secret: String, glyph: String} = "The secret {secret} was not found for {glyph}.",
+
+	InvalidPassphrase = "The passphrase doesn't unlock the secrets vault.",
+	PassphraseMismatch = "The passphrases don't match, no vault was created.",
+	Crypto = "Encryption primitives failed unexpectedly.",
+
+	IO {source: std::io::Error} = "IO operations failed unexpectedly.",
+	Decode {source: base64::DecodeError} = "The secrets vault holds malformed encoded data.",
+	FromUtf8 {source: std::string::FromUtf8Error} = "UTF-8 conversion threw an error.",
+
+	Settings {source: SettingsError} = "Unexpected error thrown in Settings module.",
+}
+
 custom_error! {pub SetupError
+	RelocationTargetExists{path: String} = "The relocation target {path} already exists.",
+	TemplateNotFound{template: String} = "The template {template} was not found.",
+	InvalidTemplate{template: String, message: String} = "The template {template} seems to be invalid: {message}.",
+
+	IO {source: std::io::Error} = "IO operations failed unexpectedly.",
+	Format {source: FormatError} = "Unexpected error thrown in Format module.",
 	Settings {source: SettingsError} = "Unexpected error thrown in Settings module.",
 	State {source: StateError} = "Unexpected error thrown in State module.",
 }
 
 custom_error! {pub CoreError
+	DisallowedHeadless{operation: String} = "The {operation} operation needs a working directory and isn't available in headless mode.",
+	InvalidEditor{editor: String} = "The editor {editor} couldn't be parsed. Check VISUAL and EDITOR.",
+	EditorFailed{editor: String, status: String} = "The editor {editor} exited with {status}, leaving settings untouched.",
+
+	Env {source: EnvError} = "Unexpected error thrown in Env module.",
+	Format {source: FormatError} = "Unexpected error thrown in Format module.",
+	Settings {source: SettingsError} = "Unexpected error thrown in Settings module.",
 	State {source: StateError} = "Unexpected error thrown in State module.",
+	IOHandler {source: IOError} = "Unexpected error thrown in IO module.",
 	Setup {source: SetupError} = "Unexpected error thrown in setting up Godwit.",
-	Plugin {source: PluginError} = "Unexpected error thrown in State module.",
+	Plugin {source: PluginError} = "Plugin failed: {source}",
+	Hook {source: HookError} = "{source}",
+	Scaffold {source: ScaffoldError} = "{source}",
+	Daemon {source: DaemonError} = "{source}",
+	Tracker {source: TrackerError} = "{source}",
+	Activity {source: ActivityError} = "{source}",
+	History {source: HistoryError} = "{source}",
+	Secret {source: SecretError} = "Unexpected error thrown in Secrets module.",
+	IO {source: std::io::Error} = "IO operations failed unexpectedly.",
 	Tui {source: TuiError} = "Unexpected error thrown in State module.",
 }
@@ -0,0 +1,376 @@
+//! File Formats
+//!
+//! Extension-driven (de)serialization for settings and state-graph files.
+//! JSON stays the default while TOML and YAML are offered for hand-edited
+//! files, picked by a trailing `.toml`, `.yaml` or `.yml` extension.
+//!
+//! Rewritten TOML files keep their comments and layout, as new values are
+//! carried over the existing document. Other commented files lose their
+//! comments when rewritten, so a copy of them is kept aside first.
+use crate::errors::FormatError;
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml_edit::{Document, Item, Table, Value as TomlValue};
+
+/// Extension of the copy kept of commented files losing their comments.
+const BACKUP_EXT: &str = "gwbak";
+
+/// On-disk formats for Godwit files.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+	Json,
+	Toml,
+	Yaml,
+}
+
+impl Format {
+	pub fn iterator() -> impl Iterator<Item = Format> {
+		[Format::Json, Format::Toml, Format::Yaml].iter().copied()
+	}
+
+	/// Returns the format of a file from its extension.
+	pub fn from_path(path: &Path) -> Format {
+		match path.extension().and_then(|ext| ext.to_str()) {
+			Some("toml") => Format::Toml,
+			Some("yaml") | Some("yml") => Format::Yaml,
+			_ => Format::Json,
+		}
+	}
+
+	/// Returns the extension appended to file names. JSON files keep their
+	/// bare Godwit names.
+	pub fn extension(&self) -> Option<&str> {
+		match self {
+			Format::Json => None,
+			Format::Toml => Some("toml"),
+			Format::Yaml => Some("yaml"),
+		}
+	}
+}
+
+impl fmt::Display for Format {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Format::Json => write!(f, "json"),
+			Format::Toml => write!(f, "toml"),
+			Format::Yaml => write!(f, "yaml"),
+		}
+	}
+}
+
+impl FromStr for Format {
+	type Err = FormatError;
+
+	fn from_str(format_str: &str) -> Result<Self, Self::Err> {
+		match format_str.trim().to_lowercase().as_str() {
+			"json" => Ok(Format::Json),
+			"toml" => Ok(Format::Toml),
+			"yaml" | "yml" => Ok(Format::Yaml),
+			_ => Err(FormatError::UnknownFormat {
+				format: format_str.into(),
+			}),
+		}
+	}
+}
+
+/// Strips a format extension from a path, leaving its Godwit name.
+pub fn strip_format(path: &Path) -> PathBuf {
+	match Format::from_path(path) {
+		Format::Json => path.to_path_buf(),
+		_ => path.with_extension(""),
+	}
+}
+
+/// Returns a path renamed for given format.
+pub fn with_format(path: &Path, format: Format) -> PathBuf {
+	let base_path = strip_format(path);
+
+	match format.extension() {
+		Some(ext) => {
+			let mut file_name = base_path.file_name().unwrap_or_default().to_os_string();
+			file_name.push(".");
+			file_name.push(ext);
+			base_path.with_file_name(file_name)
+		}
+		None => base_path,
+	}
+}
+
+/// Finds an existing file for a Godwit name in any format.
+pub fn find_variant(base_path: &Path) -> Option<PathBuf> {
+	Format::iterator()
+		.map(|format| with_format(base_path, format))
+		.chain(std::iter::once({
+			let mut file_name: OsString = base_path.file_name().unwrap_or_default().into();
+			file_name.push(".yml");
+			base_path.with_file_name(file_name)
+		}))
+		.find(|path| path.is_file())
+}
+
+/// Splits location off a parser message, which carries it inline.
+fn parse_error(line: usize, column: usize, message: String) -> FormatError {
+	FormatError::Parse {
+		line: line,
+		column: column,
+		message: message
+			.split(" at line ")
+			.next()
+			.unwrap_or_default()
+			.to_string(),
+	}
+}
+
+/// Deserializes content in given format.
+pub fn from_str<T>(content: &str, format: Format) -> Result<T, FormatError>
+where
+	T: DeserializeOwned,
+{
+	match format {
+		Format::Json => serde_json::from_str(content)
+			.map_err(|e| parse_error(e.line(), e.column(), e.to_string())),
+		Format::Toml => toml::from_str(content).map_err(|e| {
+			let (line, column) = e.line_col().unwrap_or_default();
+			parse_error(line + 1, column + 1, e.to_string())
+		}),
+		Format::Yaml => serde_yaml::from_str(content).map_err(|e| {
+			let (line, column) = e
+				.location()
+				.map(|location| (location.line(), location.column()))
+				.unwrap_or_default();
+			parse_error(line, column, e.to_string())
+		}),
+	}
+}
+
+/// Drops null values, which TOML cannot represent.
+fn strip_nulls(value: Value) -> Value {
+	match value {
+		Value::Object(map) => Value::Object(
+			map.into_iter()
+				.filter(|(_, value)| !value.is_null())
+				.map(|(key, value)| (key, strip_nulls(value)))
+				.collect(),
+		),
+		Value::Array(values) => Value::Array(values.into_iter().map(strip_nulls).collect()),
+		value => value,
+	}
+}
+
+/// Serializes a value in given format.
+pub fn to_string<T>(value: &T, format: Format) -> Result<String, FormatError>
+where
+	T: Serialize,
+{
+	match format {
+		Format::Json => Ok(serde_json::to_string_pretty(value)?),
+		Format::Toml => {
+			let value = strip_nulls(serde_json::to_value(value)?);
+			Ok(toml::to_string_pretty(&toml::Value::try_from(value)?)?)
+		}
+		Format::Yaml => Ok(serde_yaml::to_string(value)?),
+	}
+}
+
+/// Checks whether hand-edited content carries comments. A `#` only starts a
+/// comment outside of quoted scalars and after whitespace, so that URL
+/// fragments and quoted hashes don't count.
+fn has_comments(content: &str, format: Format) -> bool {
+	if format == Format::Json {
+		return false;
+	}
+
+	let mut quote = None;
+	let mut prev = '\n';
+	let mut last = '\n';
+	let mut chars = content.chars().peekable();
+
+	while let Some(c) = chars.next() {
+		match quote {
+			Some('"') if c == '\\' => {
+				chars.next();
+			}
+			Some('\'') if c == '\'' && format == Format::Yaml && chars.peek() == Some(&'\'') => {
+				chars.next();
+			}
+			Some(open) if c == open => quote = None,
+			Some(_) => (),
+			None if c == '#' && prev.is_whitespace() => return true,
+			// Quotes only open a scalar at its start, not within `it's`.
+			None if (c == '"' || c == '\'') && "\n:-[{,?=".contains(last) => quote = Some(c),
+			None => (),
+		}
+
+		if c == '\n' || !c.is_whitespace() {
+			last = c;
+		}
+		prev = c;
+	}
+
+	false
+}
+
+/// Keeps a copy of a commented file about to lose its comments.
+fn keep_comments(path: &Path, content: &str) -> Result<(), FormatError> {
+	if has_comments(content, Format::from_path(path)) {
+		let mut backup_name = path.file_name().unwrap_or_default().to_os_string();
+		backup_name.push(format!(".{}", BACKUP_EXT));
+		let backup = path.with_file_name(backup_name);
+
+		fs::write(&backup, content)?;
+		warn!(
+			"Comments of {} are dropped on rewrite. Its commented copy is kept at {}.",
+			path.display(),
+			backup.display()
+		);
+	}
+
+	Ok(())
+}
+
+/// Carries a new TOML item over an existing one, keeping the comments and
+/// layout of what remains.
+fn merge_item(old: &mut Item, new: Item) {
+	match (old, new) {
+		(Item::Table(old), Item::Table(new)) => merge_table(old, new),
+		(Item::ArrayOfTables(old), Item::ArrayOfTables(new)) => {
+			while old.len() > new.len() {
+				old.remove(old.len() - 1);
+			}
+
+			for (index, table) in new.into_iter().enumerate() {
+				match old.get_mut(index) {
+					Some(old_table) => merge_table(old_table, table),
+					None => old.push(table),
+				}
+			}
+		}
+		(Item::Value(old), Item::Value(new)) => merge_value(old, new),
+		(old, new) => *old = new,
+	}
+}
+
+/// Carries a new TOML table over an existing one.
+fn merge_table(old: &mut Table, new: Table) {
+	let stale = old
+		.iter()
+		.map(|(key, _)| key.to_string())
+		.filter(|key| !new.contains_key(key))
+		.collect::<Vec<_>>();
+
+	for key in stale {
+		old.remove(&key);
+	}
+
+	for (key, item) in new {
+		match old.get_mut(&key) {
+			Some(old_item) => merge_item(old_item, item),
+			None => {
+				old.insert(&key, item);
+			}
+		}
+	}
+}
+
+/// Carries a new TOML value over an existing one, keeping its decoration.
+fn merge_value(old: &mut TomlValue, new: TomlValue) {
+	match (old, new) {
+		(TomlValue::InlineTable(old), TomlValue::InlineTable(new)) => {
+			let stale = old
+				.iter()
+				.map(|(key, _)| key.to_string())
+				.filter(|key| !new.contains_key(key))
+				.collect::<Vec<_>>();
+
+			for key in stale {
+				old.remove(&key);
+			}
+
+			for (key, value) in new {
+				match old.get_mut(&key) {
+					Some(old_value) => merge_value(old_value, value),
+					None => {
+						old.insert(&key, value);
+					}
+				}
+			}
+		}
+		(old, mut new) => {
+			*new.decor_mut() = old.decor().clone();
+			*old = new;
+		}
+	}
+}
+
+/// Rewrites existing TOML content with new content, keeping comments.
+fn merge_toml(existing: &str, content: &str) -> Result<String, FormatError> {
+	let mut document = match existing.parse::<Document>() {
+		Ok(document) => document,
+		Err(_) => return Ok(content.to_string()),
+	};
+
+	let new_document = content.parse::<Document>()?;
+	merge_table(document.as_table_mut(), new_document.as_table().clone());
+
+	Ok(document.to_string())
+}
+
+/// Reads a file in the format given by its extension.
+pub fn read<T, P>(path: P) -> Result<T, FormatError>
+where
+	T: DeserializeOwned,
+	P: AsRef<Path>,
+{
+	let content = fs::read_to_string(&path)?;
+	from_str(&content, Format::from_path(path.as_ref()))
+}
+
+/// Writes a file in the format given by its extension.
+pub fn write<T, P>(path: P, value: &T) -> Result<(), FormatError>
+where
+	T: Serialize,
+	P: AsRef<Path>,
+{
+	let path = path.as_ref();
+	let format = Format::from_path(path);
+	let mut content = to_string(value, format)?;
+
+	if let Ok(existing) = fs::read_to_string(path) {
+		match format {
+			Format::Toml => content = merge_toml(&existing, &content)?,
+			_ => keep_comments(path, &existing)?,
+		}
+	}
+
+	fs::write(path, content)?;
+	Ok(())
+}
+
+/// Rewrites a file in another format, removing the original. Returns the
+/// new path. Comments don't carry over, so commented files are kept aside.
+pub fn convert<P>(path: P, format: Format) -> Result<PathBuf, FormatError>
+where
+	P: AsRef<Path>,
+{
+	let path = path.as_ref();
+	let target = with_format(path, format);
+
+	if target != path {
+		let content = fs::read_to_string(path)?;
+
+		keep_comments(path, &content)?;
+
+		let value: Value = from_str(&content, Format::from_path(path))?;
+		write(&target, &value)?;
+		fs::remove_file(path)?;
+	}
+
+	Ok(target)
+}
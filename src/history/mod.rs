@@ -0,0 +1,341 @@
+//! Tracked History
+//!
+//! Curates the recorded file changes of projects. Entries are numbered from
+//! one, oldest first, and edited in ranges: snipped out, squashed into one
+//! or annotated. Retention rules in settings prune old entries only when
+//! history is pruned, so entry numbers hold between edits.
+use crate::activity::{self, Change};
+use crate::errors::HistoryError;
+use crate::glyph::Glyph;
+use crate::settings;
+use chrono::{Duration, Local};
+use getter_derive::Getter;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Retention rules of tracked history. Entries older than the maximum age,
+/// in days, or past the maximum count are pruned.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Getter)]
+#[serde(rename_all = "snake_case", default, deny_unknown_fields)]
+pub struct Retention {
+	max_age: Option<u64>,
+	max_entries: Option<usize>,
+}
+
+/// Inclusive range of history entries, numbered from one. Open ends run to
+/// the first or last entry.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Range {
+	pub from: Option<usize>,
+	pub to: Option<usize>,
+}
+
+impl Range {
+	/// Returns the bounds of a range over some entries as slice indices.
+	fn bounds(&self, len: usize) -> Result<(usize, usize), HistoryError> {
+		let from = self.from.unwrap_or(1);
+		let to = self.to.unwrap_or(len);
+
+		if from == 0 || from > to || to > len {
+			return Err(HistoryError::RangeOutOfBounds {
+				range: self.to_string(),
+				len: len,
+			});
+		}
+
+		Ok((from - 1, to))
+	}
+}
+
+impl fmt::Display for Range {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let bound = |bound: Option<usize>| bound.map(|bound| bound.to_string()).unwrap_or_default();
+
+		match (self.from, self.to) {
+			(Some(from), Some(to)) if from == to => write!(f, "{}", from),
+			(from, to) => write!(f, "{}..{}", bound(from), bound(to)),
+		}
+	}
+}
+
+impl FromStr for Range {
+	type Err = HistoryError;
+
+	fn from_str(range_str: &str) -> Result<Self, Self::Err> {
+		let invalid = || HistoryError::InvalidRange {
+			range: range_str.into(),
+		};
+
+		let bound = |bound: &str| match bound.trim() {
+			"" => Ok(None),
+			bound => bound.parse::<usize>().map(Some).map_err(|_| invalid()),
+		};
+
+		match range_str.find("..") {
+			Some(index) => Ok(Range {
+				from: bound(&range_str[..index])?,
+				to: bound(&range_str[index + 2..])?,
+			}),
+			None => {
+				let entry = bound(range_str)?.ok_or_else(invalid)?;
+				Ok(Range {
+					from: Some(entry),
+					to: Some(entry),
+				})
+			}
+		}
+	}
+}
+
+/// Loads the history of a project, oldest first.
+pub fn load(glyph: &Glyph) -> Result<Vec<Change>, HistoryError> {
+	let mut entries = activity::load_changes(glyph)?;
+	entries.sort_by_key(|entry| entry.get_time());
+	Ok(entries)
+}
+
+/// Applies retention rules to some history, returning pruned entries.
+fn retain(entries: &mut Vec<Change>, retention: &Retention) -> usize {
+	let len = entries.len();
+
+	if let Some(max_age) = retention.max_age {
+		let oldest = Local::now() - Duration::days(max_age as i64);
+		entries.retain(|entry| entry.get_until().unwrap_or_else(|| entry.get_time()) >= oldest);
+	}
+
+	if let Some(max_entries) = retention.max_entries {
+		let excess = entries.len().saturating_sub(max_entries);
+		entries.drain(..excess);
+	}
+
+	len - entries.len()
+}
+
+/// Replaces a range of entries with the entries returned. Returns the number
+/// of entries replaced.
+fn splice<F>(entries: &mut Vec<Change>, range: Range, replace: F) -> Result<usize, HistoryError>
+where
+	F: FnOnce(Vec<Change>) -> Vec<Change>,
+{
+	let (from, to) = range.bounds(entries.len())?;
+
+	let edited = entries.drain(from..to).collect::<Vec<_>>();
+	let count = edited.len();
+
+	let tail = entries.split_off(from);
+	entries.extend(replace(edited));
+	entries.extend(tail);
+
+	Ok(count)
+}
+
+/// Edits a range of the history of a project, replacing it with the entries
+/// returned. Returns the number of entries edited.
+fn edit<F>(glyph: &Glyph, range: Range, replace: F) -> Result<usize, HistoryError>
+where
+	F: FnOnce(Vec<Change>) -> Vec<Change>,
+{
+	activity::update_changes(glyph, |entries| splice(entries, range, replace))
+}
+
+/// Drops a range of entries from the history of a project.
+pub fn snip(glyph: &Glyph, range: Range) -> Result<usize, HistoryError> {
+	edit(glyph, range, |_| Vec::new())
+}
+
+/// Squashes a range of entries into one, optionally noted.
+pub fn squash(glyph: &Glyph, range: Range, note: Option<String>) -> Result<usize, HistoryError> {
+	edit(glyph, range, |edited| {
+		Change::squash(&edited, note).into_iter().collect()
+	})
+}
+
+/// Notes a range of entries, clearing notes when none is given.
+pub fn annotate(glyph: &Glyph, range: Range, note: Option<String>) -> Result<usize, HistoryError> {
+	edit(glyph, range, |mut edited| {
+		for entry in &mut edited {
+			entry.annotate(note.clone());
+		}
+		edited
+	})
+}
+
+/// Applies retention rules to the history of every project. Returns the
+/// number of entries pruned.
+pub fn prune() -> Result<usize, HistoryError> {
+	let retention = settings::get_settings()?.get_retention();
+	let mut pruned = 0;
+
+	for glyph in activity::active_glyphs()? {
+		let count = activity::update_changes(&glyph, |entries| {
+			Ok::<_, HistoryError>(retain(entries, &retention))
+		})?;
+
+		if count > 0 {
+			debug!("Pruned {} history entries of {}.", count, glyph);
+			pruned += count;
+		}
+	}
+
+	Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::activity::ChangeKind;
+	use chrono::DateTime;
+	use std::path::PathBuf;
+
+	/// Returns a change of a file at some minute.
+	fn change(path: &str, minute: u32) -> Change {
+		Change::new(
+			"@org/svc".parse().unwrap(),
+			PathBuf::from(path),
+			ChangeKind::Modified,
+			DateTime::parse_from_rfc3339(&format!("2020-08-30T10:{:02}:00+02:00", minute))
+				.unwrap()
+				.with_timezone(&Local),
+		)
+	}
+
+	/// Parses a range.
+	fn range(range_str: &str) -> Range {
+		range_str.parse().unwrap()
+	}
+
+	#[test]
+	fn ranges_parse_closed_and_single_entries() {
+		assert_eq!(
+			range("4..9"),
+			Range {
+				from: Some(4),
+				to: Some(9)
+			}
+		);
+		assert_eq!(
+			range(" 4 .. 9 "),
+			Range {
+				from: Some(4),
+				to: Some(9)
+			}
+		);
+		assert_eq!(
+			range("3"),
+			Range {
+				from: Some(3),
+				to: Some(3)
+			}
+		);
+		assert_eq!(range("3").to_string(), "3");
+		assert_eq!(range("4..9").to_string(), "4..9");
+	}
+
+	#[test]
+	fn ranges_parse_open_ends() {
+		assert_eq!(
+			range("12.."),
+			Range {
+				from: Some(12),
+				to: None
+			}
+		);
+		assert_eq!(
+			range("..5"),
+			Range {
+				from: None,
+				to: Some(5)
+			}
+		);
+		assert_eq!(
+			range(".."),
+			Range {
+				from: None,
+				to: None
+			}
+		);
+		assert_eq!(range("12..").to_string(), "12..");
+
+		assert_eq!(range("2..").bounds(5).unwrap(), (1, 5));
+		assert_eq!(range("..2").bounds(5).unwrap(), (0, 2));
+		assert_eq!(range("..").bounds(5).unwrap(), (0, 5));
+	}
+
+	#[test]
+	fn invalid_ranges_are_refused() {
+		for range_str in &["", "a", "1..b", "-1..3", "1...3"] {
+			match range_str.parse::<Range>() {
+				Err(HistoryError::InvalidRange { range }) => assert_eq!(&range, range_str),
+				result => panic!("{:?} parsed as {:?}", range_str, result.ok()),
+			}
+		}
+	}
+
+	#[test]
+	fn out_of_bounds_ranges_are_refused() {
+		for range_str in &["0..2", "4..2", "2..6", "6"] {
+			match range(range_str).bounds(5) {
+				Err(HistoryError::RangeOutOfBounds { len, .. }) => assert_eq!(len, 5),
+				result => panic!("{:?} fit as {:?}", range_str, result.ok()),
+			}
+		}
+	}
+
+	#[test]
+	fn squash_merges_adjacent_entries() {
+		let mut entries = vec![
+			change("README.md", 0),
+			change("src/a.rs", 1),
+			change("src/b.rs", 2),
+			change("src/a.rs", 3),
+			change("Cargo.toml", 4),
+		];
+
+		let count = splice(&mut entries, range("2..4"), |edited| {
+			Change::squash(&edited, Some(String::from("Parser rewrite")))
+				.into_iter()
+				.collect()
+		})
+		.unwrap();
+
+		assert_eq!(count, 3);
+		assert_eq!(entries.len(), 3);
+		assert_eq!(entries[0], change("README.md", 0));
+		assert_eq!(entries[2], change("Cargo.toml", 4));
+
+		let squashed = &entries[1];
+		assert_eq!(squashed.get_path(), PathBuf::from("src"));
+		assert_eq!(
+			squashed.get_squashed(),
+			vec![PathBuf::from("src/a.rs"), PathBuf::from("src/b.rs")]
+		);
+		assert_eq!(squashed.get_time(), change("", 1).get_time());
+		assert_eq!(squashed.get_until(), Some(change("", 3).get_time()));
+		assert_eq!(squashed.get_note(), Some(String::from("Parser rewrite")));
+	}
+
+	#[test]
+	fn squash_merges_squashed_entries() {
+		let mut entries = vec![
+			change("src/a.rs", 0),
+			change("src/b.rs", 1),
+			change("docs/c.md", 2),
+		];
+
+		splice(&mut entries, range("1..2"), |edited| {
+			Change::squash(&edited, None).into_iter().collect()
+		})
+		.unwrap();
+		splice(&mut entries, range(".."), |edited| {
+			Change::squash(&edited, None).into_iter().collect()
+		})
+		.unwrap();
+
+		assert_eq!(entries.len(), 1);
+		assert_eq!(entries[0].get_path(), PathBuf::new());
+		assert_eq!(entries[0].get_squashed().len(), 3);
+		assert_eq!(entries[0].get_until(), Some(change("", 2).get_time()));
+	}
+}
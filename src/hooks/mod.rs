@@ -0,0 +1,291 @@
+//! Lifecycle Hooks
+//!
+//! Single dispatcher of everything run around core operations. Hook actions,
+//! scripts and plugins, are configured in settings, applying to every
+//! project, and per state. Settings hooks run before state hooks. Once an
+//! operation is done, its event is then fired on the plugin registry, which
+//! reaches built-ins and plugins subscribed to it.
+//!
+//! A failing pre-hook aborts its operation while a failing post-hook is only
+//! reported. Commands are run like plugins, within `hook_timeout` seconds if
+//! set, and recorded in the audit log under the hook name. Like plugins,
+//! their output goes to stderr in export mode.
+use crate::errors::{HookError, PluginError};
+use crate::glyph::Glyph;
+use crate::plugins::command::Placeholders;
+use crate::plugins::exec::ExecPlugin;
+use crate::plugins::{self, process, protocol, Event};
+use crate::settings;
+use crate::statehandler::State;
+use getter_derive::Getter;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Point of a hook relative to its operation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Stage {
+	Pre,
+	Post,
+}
+
+impl fmt::Display for Stage {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Stage::Pre => write!(f, "pre"),
+			Stage::Post => write!(f, "post"),
+		}
+	}
+}
+
+/// Operations that can be hooked. Leaving fires on the previously active
+/// project of a switch. Listing projects is only heard of by plugins.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Operation {
+	Init,
+	Add,
+	Switch,
+	Leave,
+	Remove,
+	Status,
+}
+
+impl fmt::Display for Operation {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Operation::Init => write!(f, "init"),
+			Operation::Add => write!(f, "add"),
+			Operation::Switch => write!(f, "switch"),
+			Operation::Leave => write!(f, "leave"),
+			Operation::Remove => write!(f, "remove"),
+			Operation::Status => write!(f, "status"),
+		}
+	}
+}
+
+/// Single hook action.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+	/// Shell command.
+	Run(String),
+	/// Configured plugin, called with the hook name.
+	Plugin(String),
+}
+
+impl fmt::Display for Action {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Action::Run(command) => write!(f, "{}", command),
+			Action::Plugin(plugin) => write!(f, "plugin {}", plugin),
+		}
+	}
+}
+
+/// Hook actions by stage and operation.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Getter)]
+#[serde(rename_all = "snake_case", default, deny_unknown_fields)]
+pub struct Hooks {
+	pre_init: Vec<Action>,
+	post_init: Vec<Action>,
+	pre_add: Vec<Action>,
+	post_add: Vec<Action>,
+	pre_switch: Vec<Action>,
+	post_switch: Vec<Action>,
+	pre_leave: Vec<Action>,
+	post_leave: Vec<Action>,
+	pre_remove: Vec<Action>,
+	post_remove: Vec<Action>,
+}
+
+impl Hooks {
+	/// Returns actions of a hook.
+	pub fn actions(&self, stage: Stage, operation: Operation) -> &[Action] {
+		match (stage, operation) {
+			(Stage::Pre, Operation::Init) => &self.pre_init,
+			(Stage::Post, Operation::Init) => &self.post_init,
+			(Stage::Pre, Operation::Add) => &self.pre_add,
+			(Stage::Post, Operation::Add) => &self.post_add,
+			(Stage::Pre, Operation::Switch) => &self.pre_switch,
+			(Stage::Post, Operation::Switch) => &self.post_switch,
+			(Stage::Pre, Operation::Leave) => &self.pre_leave,
+			(Stage::Post, Operation::Leave) => &self.post_leave,
+			(Stage::Pre, Operation::Remove) => &self.pre_remove,
+			(Stage::Post, Operation::Remove) => &self.post_remove,
+			(_, Operation::Status) => &[],
+		}
+	}
+
+	/// Checks whether no hook is set.
+	pub fn is_empty(&self) -> bool {
+		self == &Hooks::default()
+	}
+}
+
+/// Context exposed to hooks.
+#[derive(Clone, Debug, Default)]
+pub struct HookContext {
+	pub glyph: Option<Glyph>,
+	pub old_dir: Option<PathBuf>,
+	pub new_dir: Option<PathBuf>,
+	pub active: Option<Glyph>,
+}
+
+impl HookContext {
+	/// Returns environment variables describing the context.
+	fn vars(&self, hook: &str) -> BTreeMap<String, String> {
+		let mut vars = BTreeMap::new();
+
+		vars.insert(String::from("GODWIT_HOOK"), hook.to_string());
+
+		let entries = vec![
+			("GODWIT_GLYPH", self.glyph.as_ref().map(Glyph::to_string)),
+			(
+				"GODWIT_OLD_DIR",
+				self.old_dir
+					.as_ref()
+					.map(|dir| dir.to_string_lossy().into_owned()),
+			),
+			(
+				"GODWIT_NEW_DIR",
+				self.new_dir
+					.as_ref()
+					.map(|dir| dir.to_string_lossy().into_owned()),
+			),
+			("GODWIT_ACTIVE", self.active.as_ref().map(Glyph::to_string)),
+		];
+
+		for (var, value) in entries {
+			vars.insert(var.to_string(), value.unwrap_or_default());
+		}
+
+		vars
+	}
+
+	/// Returns the directory hooks run in.
+	fn dir(&self) -> Option<PathBuf> {
+		self.new_dir
+			.iter()
+			.chain(self.old_dir.iter())
+			.find(|dir| dir.is_dir())
+			.cloned()
+	}
+}
+
+/// Runs one action.
+fn run_action(action: &Action, hook: &str, context: &HookContext) -> Result<(), HookError> {
+	info!("Running {} hook: {}", hook, action);
+
+	match action {
+		Action::Run(command_str) => run_command(command_str, hook, context),
+		Action::Plugin(plugin) => {
+			let plugin = match plugins::get_plugin(plugin) {
+				Err(PluginError::PluginDisabled { plugin }) => {
+					info!("Skipping {} hook of disabled plugin {}.", hook, plugin);
+					return Ok(());
+				}
+				plugin => plugin?,
+			};
+
+			let mut call_context = protocol::Context::from_state(None)?;
+			call_context.glyph = context.glyph.clone();
+			call_context.directory = context.new_dir.clone().or_else(|| context.old_dir.clone());
+			call_context.prev_directory = context.old_dir.clone();
+
+			ExecPlugin::new(plugin).call_hook(hook, call_context, context.vars(hook))?;
+			Ok(())
+		}
+	}
+}
+
+/// Runs a hook command through the shell, like a plugin named after the
+/// hook.
+fn run_command(command_str: &str, hook: &str, context: &HookContext) -> Result<(), HookError> {
+	let mut command = if cfg!(target_os = "windows") {
+		let mut command = Command::new("cmd");
+		command.arg("/C");
+		command
+	} else {
+		let mut command = Command::new("sh");
+		command.arg("-c");
+		command
+	};
+	command.arg(command_str);
+
+	if let Some(dir) = context.dir() {
+		command.current_dir(dir);
+	}
+
+	command.envs(context.vars(hook));
+
+	let mut plugin = plugins::new(&format!("{} hook", hook), command_str);
+	plugin.timeout(settings::get_settings()?.get_hook_timeout());
+
+	let placeholders = Placeholders {
+		glyph: context.glyph.clone(),
+		args: vec![command_str.to_string()],
+		..Default::default()
+	};
+
+	let status = process::run(&plugin, command, &placeholders)?.status;
+
+	if !status.success() {
+		return Err(HookError::Failed {
+			hook: hook.to_string(),
+			action: command_str.to_string(),
+			status: status.to_string(),
+		});
+	}
+
+	Ok(())
+}
+
+/// Runs settings hooks, then hooks of given states. Pre-hooks stop at the
+/// first failure, post-hook failures are logged.
+fn run_hooks(
+	stage: Stage,
+	operation: Operation,
+	context: &HookContext,
+	states: &[&State],
+) -> Result<(), HookError> {
+	let hook = format!("{}_{}", stage, operation);
+
+	let mut actions = settings::get_settings()?
+		.get_hooks()
+		.actions(stage, operation)
+		.to_vec();
+
+	for state in states {
+		actions.extend_from_slice(state.get_hooks().actions(stage, operation));
+	}
+
+	for action in actions {
+		match run_action(&action, &hook, context) {
+			Err(e) if stage == Stage::Post => warn!("{}", e),
+			result => result?,
+		}
+	}
+
+	Ok(())
+}
+
+/// Dispatches a hook point: hook actions of settings and given states, then
+/// the event of a done operation on the plugin registry.
+pub fn dispatch(
+	stage: Stage,
+	operation: Operation,
+	context: &HookContext,
+	states: &[&State],
+	event: Option<Event>,
+) -> Result<(), HookError> {
+	run_hooks(stage, operation, context, states)?;
+
+	if let Some(event) = event {
+		plugins::registry()?.fire(&event)?;
+	}
+
+	Ok(())
+}
@@ -1,19 +1,21 @@
 //! Printer
 //!
 //! Controls input and read operations. Utility abstraction over general stdio input/read operations.
+use crate::activity::Change;
+use crate::daemon::Notification;
 use crate::errors::{IOError, StateError};
+use crate::plugins::{audit::Entry, manage::Health, PluginConfig};
+use crate::settings::layers::Origin;
 use crate::statehandler::State;
+use crate::tracker::{Period, Row};
+use chrono::Duration;
 use prettytable::{cell, format, row, Table};
+use serde_json::Value;
+use std::path::Path;
 
-/// Print pretty state-graphs.
-pub fn print_state_graph(state_list: Vec<State>, verbose: bool) -> Result<(), IOError> {
-	if state_list.is_empty() {
-		return Err(StateError::EmptyStateList.into());
-	}
-
-	let mut table = Table::new();
-
-	let format = format::FormatBuilder::new()
+/// Returns the box-drawn table format shared by printers.
+fn table_format() -> format::TableFormat {
+	format::FormatBuilder::new()
 		.column_separator('│')
 		.borders('│')
 		.separator(
@@ -29,7 +31,18 @@ pub fn print_state_graph(state_list: Vec<State>, verbose: bool) -> Result<(), IO
 			format::LineSeparator::new('─', '┴', '└', '┘'),
 		)
 		.padding(1, 1)
-		.build();
+		.build()
+}
+
+/// Print pretty state-graphs.
+pub fn print_state_graph(state_list: Vec<State>, verbose: bool) -> Result<(), IOError> {
+	if state_list.is_empty() {
+		return Err(StateError::EmptyStateList.into());
+	}
+
+	let mut table = Table::new();
+
+	let format = table_format();
 
 	table.set_format(format);
 
@@ -57,3 +70,249 @@ pub fn print_state_graph(state_list: Vec<State>, verbose: bool) -> Result<(), IO
 	table.printstd();
 	Ok(())
 }
+
+/// Print effective settings, optionally with their origin.
+pub fn print_settings(entries: Vec<(String, Value, Origin)>, origin: bool) -> Result<(), IOError> {
+	let mut table = Table::new();
+
+	table.set_format(table_format());
+
+	let display_value = |value: Value| match value {
+		Value::String(value) => value,
+		value => value.to_string(),
+	};
+
+	if origin {
+		table.set_titles(row![bic => "Key", "Value", "Origin"]);
+
+		for (key, value, origin) in entries {
+			table.add_row(row![key, display_value(value), origin]);
+		}
+	} else {
+		table.set_titles(row![bic => "Key", "Value"]);
+
+		for (key, value, _) in entries {
+			table.add_row(row![key, display_value(value)]);
+		}
+	}
+
+	table.printstd();
+	Ok(())
+}
+
+/// Print configured plugins along with their health.
+pub fn print_plugins(plugins: Vec<(PluginConfig, Health)>) -> Result<(), IOError> {
+	let mut table = Table::new();
+
+	table.set_format(table_format());
+
+	table.set_titles(row![bic => "Plugin", "Exec", "Hooks", "Enabled", "Health"]);
+
+	for (plugin, health) in plugins {
+		let hooks = plugin
+			.get_hooks()
+			.iter()
+			.map(ToString::to_string)
+			.collect::<Vec<_>>()
+			.join(", ");
+
+		let health_str = match (&health.exec, &health.protocol) {
+			(None, _) => String::from("exec not found"),
+			(Some(_), Some(Ok(version))) => format!("protocol v{}", version),
+			(Some(_), Some(Err(e))) => e.clone(),
+			(Some(_), None) => String::from("ok"),
+		};
+
+		table.add_row(row![
+			plugin.get_name(),
+			plugin.get_exec(),
+			hooks,
+			if plugin.get_enabled() { "yes" } else { "no" },
+			health_str,
+		]);
+	}
+
+	table.printstd();
+	Ok(())
+}
+
+/// Print recorded plugin runs, one per line. Failures are followed by their
+/// error or the last line of their stderr.
+pub fn print_plugin_log(entries: Vec<Entry>) -> Result<(), IOError> {
+	for entry in entries {
+		let outcome = match (entry.get_success(), entry.get_status()) {
+			(true, _) => String::from("ok"),
+			(false, Some(code)) => format!("exit {}", code),
+			(false, None) => String::from("failed"),
+		};
+
+		println!(
+			"{} {} {} [{}] {} in {}ms",
+			entry.get_started().format("%Y-%m-%d %H:%M:%S"),
+			entry.get_plugin(),
+			entry
+				.get_glyph()
+				.map_or_else(|| String::from("-"), |glyph| glyph.to_string()),
+			entry.get_args().join(" "),
+			outcome,
+			(entry.get_ended() - entry.get_started()).num_milliseconds(),
+		);
+
+		if !entry.get_success() {
+			let stderr = entry.get_stderr();
+			let detail = entry
+				.get_error()
+				.or_else(|| stderr.trim().lines().last().map(String::from));
+
+			if let Some(detail) = detail {
+				println!("    {}", detail);
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// Formats a duration in hours and minutes.
+fn format_duration(duration: Duration) -> String {
+	format!(
+		"{}h {:02}m",
+		duration.num_hours(),
+		duration.num_minutes() % 60
+	)
+}
+
+/// Print time spent per project, one per line, within periods when grouped.
+/// Each project is followed by its total.
+pub fn print_time_report(rows: Vec<Row>, by: Option<Period>) -> Result<(), IOError> {
+	if rows.is_empty() {
+		println!("No time tracked.");
+		return Ok(());
+	}
+
+	let width = rows
+		.iter()
+		.map(|row| row.get_glyph().to_string().len())
+		.max()
+		.unwrap_or_default();
+	let mut total = Duration::zero();
+
+	for (index, row) in rows.iter().enumerate() {
+		total += row.get_duration();
+
+		match (by, row.get_period()) {
+			(Some(by), Some(period)) => println!(
+				"{:<width$} {:<18} {:>10}",
+				row.get_glyph().to_string(),
+				match by {
+					Period::Day => period.format("%Y-%m-%d").to_string(),
+					Period::Week => period.format("week of %Y-%m-%d").to_string(),
+				},
+				format_duration(row.get_duration()),
+				width = width,
+			),
+			_ => println!(
+				"{:<width$} {:>10}",
+				row.get_glyph().to_string(),
+				format_duration(row.get_duration()),
+				width = width,
+			),
+		}
+
+		let last_of_glyph = rows
+			.get(index + 1)
+			.map_or(true, |next| next.get_glyph() != row.get_glyph());
+
+		if by.is_some() && last_of_glyph {
+			println!(
+				"{:<width$} {:<18} {:>10}",
+				row.get_glyph().to_string(),
+				"total",
+				format_duration(total),
+				width = width,
+			);
+			total = Duration::zero();
+		}
+	}
+
+	Ok(())
+}
+
+/// Describes a change, squashed ones by the number of files they touched.
+fn describe_change(change: &Change) -> String {
+	let mut description = match change.get_squashed().len() {
+		0 => format!("{:<8} {}", change.get_kind(), change.get_path().display()),
+		count => format!(
+			"{:<8} {} files under {}",
+			"squashed",
+			count,
+			Path::new(".").join(change.get_path()).display()
+		),
+	};
+
+	if let Some(until) = change.get_until() {
+		description += &until.format(" until %Y-%m-%d %H:%M:%S").to_string();
+	}
+
+	if let Some(note) = change.get_note() {
+		description += &format!("  # {}", note);
+	}
+
+	description
+}
+
+/// Print a file change on a line.
+pub fn print_change(change: &Change) {
+	println!(
+		"{} {} {}",
+		change.get_time().format("%Y-%m-%d %H:%M:%S"),
+		change.get_glyph(),
+		describe_change(change),
+	);
+}
+
+/// Print a project event the daemon was notified of.
+pub fn print_notification(notification: Notification) {
+	println!(
+		"{} {} {}",
+		notification.get_time().format("%Y-%m-%d %H:%M:%S"),
+		notification.get_event(),
+		notification.get_glyph(),
+	);
+}
+
+/// Print a timeline of file changes, oldest first.
+pub fn print_activity(changes: Vec<Change>) -> Result<(), IOError> {
+	if changes.is_empty() {
+		println!("No activity recorded.");
+		return Ok(());
+	}
+
+	for change in changes {
+		print_change(&change);
+	}
+
+	Ok(())
+}
+
+/// Print the history of a project, entries numbered from one.
+pub fn print_history(entries: Vec<Change>) -> Result<(), IOError> {
+	if entries.is_empty() {
+		println!("No history tracked.");
+		return Ok(());
+	}
+
+	let width = entries.len().to_string().len();
+
+	for (index, entry) in entries.iter().enumerate() {
+		println!(
+			"{:>width$} {} {}",
+			index + 1,
+			entry.get_time().format("%Y-%m-%d %H:%M:%S"),
+			describe_change(entry),
+			width = width,
+		);
+	}
+
+	Ok(())
+}
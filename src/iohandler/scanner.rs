@@ -3,7 +3,7 @@
 //! Controls output and write operations. Utility abstraction over general stdio output/write operations.
 use crate::errors::IOError;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Lines};
+use std::io::{self, BufRead, BufReader, Lines, Write};
 use std::path::Path;
 
 /// Collect bytes from ANSI document and return io::Lines.
@@ -37,3 +37,34 @@ pub fn parse_art(filename: &str, width: usize) -> Result<Vec<String>, IOError> {
 
 	Ok(linevec)
 }
+
+/// Ask a yes/no question on stdio. Defaults to yes on empty input, and to
+/// no once input has ended.
+pub fn confirm(prompt: &str) -> Result<bool, IOError> {
+	print!("{} [Y/n] ", prompt);
+	io::stdout().flush()?;
+
+	let mut answer = String::new();
+	if io::stdin().read_line(&mut answer)? == 0 {
+		return Ok(false);
+	}
+
+	Ok(!answer.trim().to_lowercase().starts_with('n'))
+}
+
+/// Ask for a line of input on stdio. Returns the default on empty input.
+pub fn ask(prompt: &str, default: Option<&str>) -> Result<String, IOError> {
+	match default {
+		Some(default) if !default.is_empty() => print!("{} [{}] ", prompt, default),
+		_ => print!("{} ", prompt),
+	}
+	io::stdout().flush()?;
+
+	let mut answer = String::new();
+	io::stdin().read_line(&mut answer)?;
+
+	match answer.trim() {
+		"" => Ok(default.unwrap_or_default().to_string()),
+		answer => Ok(answer.to_string()),
+	}
+}
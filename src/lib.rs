@@ -1,10 +1,20 @@
-pub mod _utils; // TODO: Ugly naming fix for fmt
+// Struct fields are initialized explicitly throughout, as in `glyph: glyph`.
+#![allow(clippy::redundant_field_names)]
+
+pub mod activity;
 pub mod core;
+pub mod daemon;
 pub mod env;
 pub mod errors;
+pub mod format;
 pub mod glyph;
+pub mod history;
+pub mod hooks;
 pub mod iohandler;
 pub mod plugins;
+pub mod scaffold;
+pub mod secrets;
 pub mod settings;
 pub mod statehandler;
+pub mod tracker;
 pub mod tui;
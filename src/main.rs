@@ -1,10 +1,17 @@
 // TODO: Documentation
 // TODO: Pattern matching for errors in this file
+#![allow(clippy::redundant_field_names)]
+use chrono::NaiveDate;
 use godwit::{glyph::Glyph, *};
 use log::{debug, error, info};
+use serde_json::Value;
 use simplelog::*;
 use std::path::PathBuf;
-use structopt::{clap::Shell, StructOpt};
+use std::process;
+use structopt::{
+	clap::{AppSettings, Shell},
+	StructOpt,
+};
 
 /// Define CLI syntaxes.
 #[derive(Debug, StructOpt)]
@@ -27,6 +34,14 @@ struct CliArgs {
 	)]
 	verbose: u64,
 
+	/// Preview shell configuration edits as a diff without writing them
+	#[structopt(long, global = true)]
+	dry_run: bool,
+
+	/// Godwit working directory or settings file (Overrides GODWIT_HOME and .gwrc)
+	#[structopt(long, global = true, parse(from_os_str))]
+	config: Option<PathBuf>,
+
 	/// Organization (for all operations) (Overrides glyph)
 	#[structopt(
 		short,
@@ -58,16 +73,25 @@ struct CliArgs {
 enum OpsEnum {
 	/// Setup Godwit working directory
 	Init {
-		/// Target path to godwit directory (Will bind a symlink)
+		/// Target path to godwit directory (Binds ~/.godwit as a symlink to it)
 		target: Option<PathBuf>,
 
 		/// Headless setup
 		#[structopt(long)]
 		headless: bool,
 
-		/// Purge existing settings before setup
+		/// Purge existing settings and state-graphs before setup, keeping tracked data
 		#[structopt(long)]
 		refresh: bool,
+
+		/// Template name or path for settings and state-graph
+		#[structopt(long)]
+		template: Option<String>,
+	},
+	/// Move Godwit working directory and rebind its symlink
+	Relocate {
+		/// New path to godwit directory
+		target: PathBuf,
 	},
 	/// Switch to target glyph, organization or project
 	Switch {
@@ -88,10 +112,47 @@ enum OpsEnum {
 		/// Working path for project
 		location: PathBuf,
 
+		/// Project environment variable (KEY=VALUE)
+		#[structopt(long = "env", parse(try_from_str = parse_key_val), number_of_values = 1)]
+		env: Vec<(String, String)>,
+
 		/// Add existing project (Doesn't trigger weaver)
 		#[structopt(short, long)]
 		existing: bool,
 
+		/// Scaffolding template passed to weaver
+		#[structopt(long, conflicts_with = "existing")]
+		template: Option<String>,
+
+		/// Add as active project
+		#[structopt(long)]
+		active: bool,
+
+		/// Add as default project
+		#[structopt(long)]
+		default: bool,
+	},
+	/// Scaffold a new project from a template and add it under Godwit
+	New {
+		/// Glyph (@organization/project)
+		#[structopt(required_unless_all = &["project", "organization"], conflicts_with_all = &["project", "organization"])]
+		glyph: Option<Glyph>,
+
+		/// Working path for project
+		location: PathBuf,
+
+		/// Template name or path
+		#[structopt(long)]
+		template: String,
+
+		/// Template prompt value (NAME=VALUE)
+		#[structopt(long = "var", parse(try_from_str = parse_key_val), number_of_values = 1)]
+		vars: Vec<(String, String)>,
+
+		/// Project environment variable (KEY=VALUE)
+		#[structopt(long = "env", parse(try_from_str = parse_key_val), number_of_values = 1)]
+		env: Vec<(String, String)>,
+
 		/// Add as active project
 		#[structopt(long)]
 		active: bool,
@@ -108,6 +169,324 @@ enum OpsEnum {
 	},
 	/// Display Godwit's status
 	Status,
+	/// Report time spent on projects
+	Time {
+		/// Glyph (@organization/project), every tracked project if omitted
+		glyph: Option<Glyph>,
+
+		/// Count time from this day on (YYYY-MM-DD)
+		#[structopt(long)]
+		since: Option<NaiveDate>,
+
+		/// Split time by period (day or week)
+		#[structopt(long)]
+		by: Option<tracker::Period>,
+	},
+	/// Show a timeline of file changes in projects
+	Activity {
+		/// Glyph (@organization/project), every project if omitted
+		glyph: Option<Glyph>,
+
+		/// Number of last changes to show
+		#[structopt(short = "n", long, default_value = "50")]
+		limit: usize,
+
+		/// Watch projects, recording and printing changes as they come (Nothing is recorded otherwise)
+		#[structopt(long, conflicts_with_all = &["glyph", "limit"])]
+		watch: bool,
+	},
+	/// View and curate the tracked history of projects
+	History {
+		#[structopt(subcommand)]
+		operation: HistoryOpsEnum,
+	},
+	/// Manage shell environment edits
+	Env {
+		#[structopt(subcommand)]
+		operation: EnvOpsEnum,
+	},
+	/// Inspect and change Godwit settings
+	Config {
+		#[structopt(subcommand)]
+		operation: ConfigOpsEnum,
+	},
+	/// Convert settings and state-graph files to another format
+	Convert {
+		/// Target format (json, toml or yaml)
+		format: format::Format,
+	},
+	/// Manage encrypted project secrets (Uses active project unless selected)
+	Secret {
+		#[structopt(subcommand)]
+		operation: SecretOpsEnum,
+	},
+	/// Run a command in the active project with its environment and secrets
+	#[structopt(setting = AppSettings::TrailingVarArg)]
+	Run {
+		/// Command and arguments
+		#[structopt(required = true)]
+		command: Vec<String>,
+	},
+	/// Run a command in every project with its environment and secrets
+	#[structopt(setting = AppSettings::TrailingVarArg)]
+	Foreach {
+		/// Command and arguments
+		#[structopt(required = true)]
+		command: Vec<String>,
+	},
+	/// Print shell exports of the active project environment and secrets
+	ShellInit,
+	/// Manage plugins
+	Plugin {
+		#[structopt(subcommand)]
+		operation: PluginOpsEnum,
+	},
+	/// Control the background daemon
+	Daemon {
+		#[structopt(subcommand)]
+		operation: DaemonOpsEnum,
+	},
+	#[structopt(external_subcommand)]
+	External(Vec<String>),
+}
+
+/// CLI call enum for settings operations.
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Config operation selection")]
+enum ConfigOpsEnum {
+	/// Display effective settings
+	Show {
+		/// Display where each value comes from
+		#[structopt(long)]
+		origin: bool,
+	},
+	/// Print effective value of a key
+	Get {
+		/// Dotted settings key (e.g. switch_on_add, env.AWS_PROFILE)
+		key: String,
+	},
+	/// Set a key in user settings
+	Set {
+		/// Dotted settings key (e.g. switch_on_add, env.AWS_PROFILE)
+		key: String,
+
+		/// Value (Typed after the current value)
+		value: String,
+	},
+	/// Edit user settings in $EDITOR
+	Edit,
+	/// Let project settings set hooks, plugins, env and directories
+	Trust {
+		/// Project settings file (Nearest one if omitted)
+		file: Option<PathBuf>,
+
+		/// Revoke trust instead
+		#[structopt(long)]
+		revoke: bool,
+	},
+	/// Validate a settings file
+	Validate {
+		/// Settings file (Validates user settings if omitted)
+		file: Option<PathBuf>,
+	},
+}
+
+/// CLI call enum for plugin operations.
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Plugin operation selection")]
+enum PluginOpsEnum {
+	/// Add a plugin to user settings
+	Add {
+		/// Unique plugin name
+		name: String,
+
+		/// Command to run the plugin
+		exec: String,
+
+		/// Lifecycle hook to subscribe to (init, add, switch, remove, status)
+		#[structopt(long = "hook", number_of_values = 1)]
+		hooks: Vec<plugins::Hook>,
+
+		/// Speak the JSON stdio protocol
+		#[structopt(long)]
+		protocol: bool,
+
+		/// Seconds the plugin may run for (Unlimited if omitted)
+		#[structopt(long)]
+		timeout: Option<u64>,
+	},
+	/// List plugins along with their health
+	List {
+		/// Start protocol plugins for a handshake
+		#[structopt(long)]
+		check: bool,
+	},
+	/// Enable a plugin
+	Enable {
+		/// Plugin name
+		name: String,
+	},
+	/// Disable a plugin without removing it
+	Disable {
+		/// Plugin name
+		name: String,
+	},
+	/// Remove a plugin from user settings
+	Rm {
+		/// Plugin name
+		name: String,
+	},
+	/// Show recorded plugin runs
+	Log {
+		/// Show failed runs alone
+		#[structopt(long)]
+		failed: bool,
+	},
+	/// Call a plugin (Protocol plugins take a method first)
+	#[structopt(setting = AppSettings::TrailingVarArg)]
+	Call {
+		/// Plugin name
+		name: String,
+
+		/// Arguments
+		args: Vec<String>,
+	},
+}
+
+/// CLI call enum for daemon operations.
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Daemon operation selection")]
+enum DaemonOpsEnum {
+	/// Start the daemon in the background
+	Start,
+	/// Stop the daemon
+	Stop,
+	/// Display whether the daemon is running
+	Status,
+	/// Print the daemon log
+	Logs {
+		/// Number of last lines to print
+		#[structopt(short = "n", long, default_value = "50")]
+		lines: usize,
+	},
+	/// Print project events as the daemon is notified of them
+	Events,
+	/// Trace the state of an application through its daemon backend
+	Trace {
+		/// Application name
+		application: String,
+
+		/// Discard what was traced before
+		#[structopt(short, long)]
+		refresh: bool,
+	},
+}
+
+/// CLI call enum for tracked history operations.
+#[derive(Debug, StructOpt)]
+#[structopt(about = "History operation selection")]
+enum HistoryOpsEnum {
+	/// Print the numbered history of a project
+	Show {
+		/// Glyph (@organization/project)
+		glyph: Glyph,
+	},
+	/// Drop a range of history entries
+	Snip {
+		/// Glyph (@organization/project)
+		glyph: Glyph,
+
+		/// Entries to drop (<from>..<to>, either end being optional)
+		range: history::Range,
+	},
+	/// Squash a range of history entries into one
+	Squash {
+		/// Glyph (@organization/project)
+		glyph: Glyph,
+
+		/// Entries to squash (<from>..<to>, either end being optional)
+		range: history::Range,
+
+		/// Note of the squashed entry
+		#[structopt(short, long)]
+		note: Option<String>,
+	},
+	/// Note a range of history entries (Clears notes if omitted)
+	Annotate {
+		/// Glyph (@organization/project)
+		glyph: Glyph,
+
+		/// Entries to note (<from>..<to>, either end being optional)
+		range: history::Range,
+
+		/// Note
+		note: Option<String>,
+	},
+	/// Prune history of every project by retention settings
+	Prune,
+}
+
+/// CLI call enum for project secrets operations.
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Secret operation selection")]
+enum SecretOpsEnum {
+	/// Store a secret (Prompts for the value, or reads it from piped stdin)
+	Set {
+		/// Secret name
+		name: String,
+	},
+	/// Print a secret
+	Get {
+		/// Secret name
+		name: String,
+	},
+	/// Remove a secret
+	Rm {
+		/// Secret name
+		name: String,
+	},
+	/// List secret names
+	List,
+}
+
+/// CLI call enum for shell environment operations.
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Environment operation selection")]
+enum EnvOpsEnum {
+	/// Set an environment variable for a project
+	Set {
+		/// Glyph (@organization/project)
+		#[structopt(required_unless_all = &["project", "organization"])]
+		glyph: Option<Glyph>,
+
+		/// Variable assignment (KEY=VALUE)
+		#[structopt(parse(try_from_str = parse_key_val))]
+		assignment: (String, String),
+	},
+	/// Unset an environment variable for a project
+	Unset {
+		/// Glyph (@organization/project)
+		#[structopt(required_unless_all = &["project", "organization"])]
+		glyph: Option<Glyph>,
+
+		/// Variable name
+		key: String,
+	},
+	/// Restore shell configuration files from latest backup
+	Restore {
+		/// Shell configuration file to restore (Restores all if omitted)
+		file: Option<PathBuf>,
+	},
+}
+
+fn parse_key_val(assignment: &str) -> Result<(String, String), String> {
+	let pos = assignment
+		.find('=')
+		.ok_or_else(|| format!("No `=` found in `{}`", assignment))?;
+	Ok((
+		assignment[..pos].to_string(),
+		assignment[pos + 1..].to_string(),
+	))
 }
 
 fn get_log_level(quiet: bool, verbosity: u64) -> LevelFilter {
@@ -138,11 +517,25 @@ fn main() {
 	// Logging globals
 	let (verbose, verbosity, quiet) = (args.verbose > 0, args.verbose, args.quiet);
 
+	env::set_dry_run(args.dry_run);
+	settings::set_config_path(args.config);
+
+	// Headless usage prints shell exports, leaving stdout to them alone. User
+	// settings are read alone, since resolving layers may already warn.
+	let export_only = settings::get_user_settings()
+		.map_or(false, |settings| settings.get_headless())
+		|| matches!(args.operation, Some(OpsEnum::ShellInit));
+	env::set_export_only(export_only);
+
 	// Logger setup
 	CombinedLogger::init(vec![TermLogger::new(
 		get_log_level(quiet, verbosity),
 		Config::default(),
-		TerminalMode::Mixed,
+		if export_only {
+			TerminalMode::Stderr
+		} else {
+			TerminalMode::Mixed
+		},
 	)])
 	.unwrap();
 
@@ -151,13 +544,21 @@ fn main() {
 			target,
 			headless,
 			refresh,
+			template,
 		}) => {
 			debug!("Entered init operation.");
-			match core::init(target, headless, refresh) {
+			match core::init(target, headless, refresh, template) {
 				Ok(_) => {
 					debug!("Init operation passed.");
 					info!("Looks like we're good to go!");
 				}
+				Err(errors::CoreError::Setup { source }) => {
+					debug!("Init operation failed.\n{}", source);
+					error!(
+						"Error occured while completing setup for Godwit directories.\n{}",
+						source
+					);
+				}
 				Err(e) => {
 					debug!("Init operation failed.\n{}", e);
 					error!(
@@ -167,10 +568,24 @@ fn main() {
 				}
 			}
 		}
+		Some(OpsEnum::Relocate { target }) => {
+			debug!("Entered relocate operation.");
+			match core::relocate(target) {
+				Ok(_) => {
+					debug!("Relocate operation passed.");
+				}
+				Err(e) => {
+					debug!("Relocate operation failed.\n{}", e);
+					error!("Error occured while relocating working directory.\n{}", e);
+				}
+			}
+		}
 		Some(OpsEnum::Add {
 			existing,
+			template,
 			glyph,
 			location,
+			env,
 			active,
 			default,
 		}) => {
@@ -183,7 +598,15 @@ fn main() {
 
 			debug!("Adding {}", &glyph);
 
-			match core::add(glyph, location, existing, active, default) {
+			match core::add(
+				glyph,
+				location,
+				env.into_iter().collect(),
+				existing,
+				template,
+				active,
+				default,
+			) {
 				Ok(_) => {
 					debug!("Add operation passed.");
 					// info!("Added project {}", glyph);
@@ -194,6 +617,42 @@ fn main() {
 				}
 			}
 		}
+		Some(OpsEnum::New {
+			glyph,
+			location,
+			template,
+			vars,
+			env,
+			active,
+			default,
+		}) => {
+			debug!("Entered new operation.");
+
+			let glyph = glyph.unwrap_or_else(|| glyph::Glyph {
+				tag: organization.unwrap_or_default(),
+				id: project.unwrap_or_default(),
+			});
+
+			debug!("Scaffolding {} from {}", &glyph, &template);
+
+			match core::new(
+				glyph,
+				location,
+				template,
+				vars.into_iter().collect(),
+				env.into_iter().collect(),
+				active,
+				default,
+			) {
+				Ok(_) => {
+					debug!("New operation passed.");
+				}
+				Err(e) => {
+					debug!("New operation failed.\n{}", e);
+					error!("Error occured while scaffolding new project.\n{}", e);
+				}
+			}
+		}
 		Some(OpsEnum::Remove { glyph }) => {
 			debug!("Entered remove operation.");
 
@@ -250,6 +709,444 @@ fn main() {
 				}
 			};
 		}
+		Some(OpsEnum::Time { glyph, since, by }) => {
+			debug!("Entered time operation.");
+
+			match core::time_report(glyph, since, by) {
+				Ok(rows) => {
+					debug!("Time operation passed.");
+					iohandler::printer::print_time_report(rows, by)
+						.map_err(|e| error!("{:?}", e))
+						.ok();
+				}
+				Err(e) => {
+					debug!("Time operation failed.\n{}", e);
+					error!("Error occured while reporting time.\n{}", e);
+				}
+			}
+		}
+		Some(OpsEnum::Activity {
+			glyph,
+			limit,
+			watch,
+		}) => {
+			debug!("Entered activity operation.");
+
+			let result = if watch {
+				core::watch_activity(iohandler::printer::print_change)
+			} else {
+				core::activity(glyph, limit).map(|changes| {
+					iohandler::printer::print_activity(changes)
+						.map_err(|e| error!("{:?}", e))
+						.ok();
+				})
+			};
+
+			match result {
+				Ok(_) => {
+					debug!("Activity operation passed.");
+				}
+				Err(e) => {
+					debug!("Activity operation failed.\n{}", e);
+					error!("Error occured while tracking activity.\n{}", e);
+				}
+			}
+		}
+		Some(OpsEnum::History { operation }) => {
+			debug!("Entered history operation.");
+
+			let result = match operation {
+				HistoryOpsEnum::Show { glyph } => core::history(glyph).map(|entries| {
+					iohandler::printer::print_history(entries)
+						.map_err(|e| error!("{:?}", e))
+						.ok();
+				}),
+				HistoryOpsEnum::Snip { glyph, range } => core::snip_history(glyph, range)
+					.map(|count| println!("Snipped {} history entries.", count)),
+				HistoryOpsEnum::Squash { glyph, range, note } => {
+					core::squash_history(glyph, range, note)
+						.map(|count| println!("Squashed {} history entries.", count))
+				}
+				HistoryOpsEnum::Annotate { glyph, range, note } => {
+					core::annotate_history(glyph, range, note)
+						.map(|count| println!("Annotated {} history entries.", count))
+				}
+				HistoryOpsEnum::Prune => {
+					core::prune_history().map(|count| println!("Pruned {} history entries.", count))
+				}
+			};
+
+			match result {
+				Ok(_) => {
+					debug!("History operation passed.");
+				}
+				Err(e) => {
+					debug!("History operation failed.\n{}", e);
+					error!("Error occured while curating history.\n{}", e);
+				}
+			}
+		}
+		Some(OpsEnum::Env { operation }) => match operation {
+			EnvOpsEnum::Set {
+				glyph,
+				assignment: (key, value),
+			} => {
+				debug!("Entered env set operation.");
+
+				let glyph = glyph.unwrap_or_else(|| glyph::Glyph {
+					tag: organization.unwrap_or_default(),
+					id: project.unwrap_or_default(),
+				});
+
+				match core::set_env(glyph, key, Some(value)) {
+					Ok(_) => {
+						debug!("Env set operation passed.");
+					}
+					Err(e) => {
+						debug!("Env set operation failed.\n{}", e);
+						error!("Error occured while setting project environment.\n{}", e);
+					}
+				}
+			}
+			EnvOpsEnum::Unset { glyph, key } => {
+				debug!("Entered env unset operation.");
+
+				let glyph = glyph.unwrap_or_else(|| glyph::Glyph {
+					tag: organization.unwrap_or_default(),
+					id: project.unwrap_or_default(),
+				});
+
+				match core::set_env(glyph, key, None) {
+					Ok(_) => {
+						debug!("Env unset operation passed.");
+					}
+					Err(e) => {
+						debug!("Env unset operation failed.\n{}", e);
+						error!("Error occured while unsetting project environment.\n{}", e);
+					}
+				}
+			}
+			EnvOpsEnum::Restore { file } => {
+				debug!("Entered env restore operation.");
+
+				match core::restore_env(file) {
+					Ok(restored) => {
+						debug!("Env restore operation passed.");
+						for cfg_path in restored {
+							info!("Restored {}", cfg_path.display());
+						}
+					}
+					Err(e) => {
+						debug!("Env restore operation failed.\n{}", e);
+						error!("Error occured while restoring shell configuration.\n{}", e);
+					}
+				}
+			}
+		},
+		Some(OpsEnum::Config { operation }) => match operation {
+			ConfigOpsEnum::Show { origin } => {
+				debug!("Entered config show operation.");
+
+				match core::show_config() {
+					Ok(entries) => {
+						debug!("Config show operation passed.");
+						iohandler::printer::print_settings(entries, origin)
+							.map_err(|e| error!("{:?}", e))
+							.ok();
+					}
+					Err(e) => {
+						debug!("Config show operation failed.\n{}", e);
+						error!("Error occured while resolving settings.\n{}", e);
+					}
+				}
+			}
+			ConfigOpsEnum::Get { key } => {
+				debug!("Entered config get operation.");
+
+				match core::get_config(key) {
+					Ok(value) => {
+						debug!("Config get operation passed.");
+						match value {
+							serde_json::Value::String(value) => println!("{}", value),
+							value => println!("{}", value),
+						}
+					}
+					Err(e) => {
+						debug!("Config get operation failed.\n{}", e);
+						error!("Error occured while reading settings.\n{}", e);
+					}
+				}
+			}
+			ConfigOpsEnum::Set { key, value } => {
+				debug!("Entered config set operation.");
+
+				match core::set_config(key, value) {
+					Ok(_) => {
+						debug!("Config set operation passed.");
+					}
+					Err(errors::CoreError::Settings { source }) => {
+						debug!("Config set operation failed.\n{}", source);
+						error!("Error occured while changing settings.\n{}", source);
+					}
+					Err(e) => {
+						debug!("Config set operation failed.\n{}", e);
+						error!("Error occured while changing settings.\n{}", e);
+					}
+				}
+			}
+			ConfigOpsEnum::Edit => {
+				debug!("Entered config edit operation.");
+
+				match core::edit_config() {
+					Ok(_) => {
+						debug!("Config edit operation passed.");
+					}
+					Err(errors::CoreError::Settings { source }) => {
+						debug!("Config edit operation failed.\n{}", source);
+						error!("Error occured while editing settings.\n{}", source);
+					}
+					Err(e) => {
+						debug!("Config edit operation failed.\n{}", e);
+						error!("Error occured while editing settings.\n{}", e);
+					}
+				}
+			}
+			ConfigOpsEnum::Trust { file, revoke } => {
+				debug!("Entered config trust operation.");
+
+				match core::trust_config(file, revoke) {
+					Ok(path) if revoke => {
+						debug!("Config trust operation passed.");
+						info!("Revoked trust in {}.", path.display());
+					}
+					Ok(path) => {
+						debug!("Config trust operation passed.");
+						info!("Trusted {}.", path.display());
+					}
+					Err(e) => {
+						debug!("Config trust operation failed.\n{}", e);
+						error!("Error occured while trusting settings.\n{}", e);
+					}
+				}
+			}
+			ConfigOpsEnum::Validate { file } => {
+				debug!("Entered config validate operation.");
+
+				match core::validate_config(file) {
+					Ok(_) => {
+						debug!("Config validate operation passed.");
+						info!("Settings are valid.");
+					}
+					Err(errors::CoreError::Settings { source }) => {
+						debug!("Config validate operation failed.\n{}", source);
+						error!("Settings failed validation.\n{}", source);
+					}
+					Err(e) => {
+						debug!("Config validate operation failed.\n{}", e);
+						error!("Settings failed validation.\n{}", e);
+					}
+				}
+			}
+		},
+		Some(OpsEnum::Convert { format }) => {
+			debug!("Entered convert operation.");
+
+			match core::convert(format) {
+				Ok(converted) => {
+					debug!("Convert operation passed.");
+					for path in converted {
+						info!("Converted {}.", path.display());
+					}
+				}
+				Err(errors::CoreError::Settings { source }) => {
+					debug!("Convert operation failed.\n{}", source);
+					error!("Error occured while converting files.\n{}", source);
+				}
+				Err(e) => {
+					debug!("Convert operation failed.\n{}", e);
+					error!("Error occured while converting files.\n{}", e);
+				}
+			}
+		}
+		Some(OpsEnum::Secret { operation }) => {
+			debug!("Entered secret operation.");
+
+			let glyph = match (organization, project) {
+				(Some(tag), Some(id)) => Some(glyph::Glyph { tag: tag, id: id }),
+				_ => None,
+			};
+
+			let result = match operation {
+				SecretOpsEnum::Set { name } => core::set_secret(glyph, name),
+				SecretOpsEnum::Get { name } => {
+					core::get_secret(glyph, name).map(|value| println!("{}", value))
+				}
+				SecretOpsEnum::Rm { name } => core::remove_secret(glyph, name),
+				SecretOpsEnum::List => core::list_secrets(glyph).map(|names| {
+					for name in names {
+						println!("{}", name);
+					}
+				}),
+			};
+
+			match result {
+				Ok(_) => {
+					debug!("Secret operation passed.");
+				}
+				Err(e) => {
+					debug!("Secret operation failed.\n{}", e);
+					error!("Error occured while accessing secrets.\n{}", e);
+				}
+			}
+		}
+		Some(OpsEnum::Run { command }) => {
+			debug!("Entered run operation.");
+
+			match core::run(command) {
+				Ok(status) => {
+					debug!("Run operation passed.");
+					process::exit(status.code().unwrap_or(1));
+				}
+				Err(e) => {
+					debug!("Run operation failed.\n{}", e);
+					error!("Error occured while running command.\n{}", e);
+				}
+			}
+		}
+		Some(OpsEnum::Foreach { command }) => {
+			debug!("Entered foreach operation.");
+
+			match core::foreach(command) {
+				Ok(statuses) => {
+					debug!("Foreach operation passed.");
+
+					let failed = statuses
+						.iter()
+						.filter(|(_, status)| !status.success())
+						.collect::<Vec<_>>();
+
+					for (glyph, status) in &failed {
+						error!("Command failed in {} with {}.", glyph, status);
+					}
+
+					if !failed.is_empty() {
+						process::exit(1);
+					}
+				}
+				Err(e) => {
+					debug!("Foreach operation failed.\n{}", e);
+					error!("Error occured while running command.\n{}", e);
+				}
+			}
+		}
+		Some(OpsEnum::ShellInit) => {
+			debug!("Entered shell-init operation.");
+
+			match core::shell_init() {
+				Ok(_) => {
+					debug!("Shell-init operation passed.");
+				}
+				Err(e) => {
+					debug!("Shell-init operation failed.\n{}", e);
+					error!("Error occured while exporting environment.\n{}", e);
+				}
+			}
+		}
+		Some(OpsEnum::Plugin { operation }) => {
+			debug!("Entered plugin operation.");
+
+			let result = match operation {
+				PluginOpsEnum::Add {
+					name,
+					exec,
+					hooks,
+					protocol,
+					timeout,
+				} => core::add_plugin(name, exec, hooks, protocol, timeout),
+				PluginOpsEnum::List { check } => core::list_plugins(check).map(|plugins| {
+					iohandler::printer::print_plugins(plugins)
+						.map_err(|e| error!("{:?}", e))
+						.ok();
+				}),
+				PluginOpsEnum::Enable { name } => core::enable_plugin(name, true),
+				PluginOpsEnum::Disable { name } => core::enable_plugin(name, false),
+				PluginOpsEnum::Rm { name } => core::remove_plugin(name),
+				PluginOpsEnum::Log { failed } => core::plugin_log(failed).map(|entries| {
+					iohandler::printer::print_plugin_log(entries)
+						.map_err(|e| error!("{:?}", e))
+						.ok();
+				}),
+				PluginOpsEnum::Call { name, args } => {
+					core::call_plugin(name, args).map(|value| match value {
+						Some(Value::Null) | None => (),
+						Some(Value::String(value)) => println!("{}", value),
+						Some(value) => println!("{}", value),
+					})
+				}
+			};
+
+			match result {
+				Ok(_) => {
+					debug!("Plugin operation passed.");
+				}
+				Err(e) => {
+					debug!("Plugin operation failed.\n{}", e);
+					error!("Error occured while managing plugins.\n{}", e);
+				}
+			}
+		}
+		Some(OpsEnum::Daemon { operation }) => {
+			debug!("Entered daemon operation.");
+
+			let result = match operation {
+				DaemonOpsEnum::Start => {
+					core::start_daemon().map(|pid| println!("Daemon started with pid {}.", pid))
+				}
+				DaemonOpsEnum::Stop => core::stop_daemon().map(|_| println!("Daemon stopped.")),
+				DaemonOpsEnum::Status => core::daemon_status().map(|status| match status {
+					daemon::Status::Running { pid } => {
+						println!("Daemon is running with pid {}.", pid)
+					}
+					daemon::Status::Stopped => println!("Daemon is stopped."),
+				}),
+				DaemonOpsEnum::Logs { lines } => core::daemon_logs(lines).map(|lines| {
+					for line in lines {
+						println!("{}", line);
+					}
+				}),
+				DaemonOpsEnum::Events => {
+					core::daemon_events(iohandler::printer::print_notification)
+				}
+				DaemonOpsEnum::Trace {
+					application,
+					refresh,
+				} => core::daemon_trace(application, refresh),
+			};
+
+			match result {
+				Ok(_) => {
+					debug!("Daemon operation passed.");
+				}
+				Err(e) => {
+					debug!("Daemon operation failed.\n{}", e);
+					error!("Error occured while controlling the daemon.\n{}", e);
+				}
+			}
+		}
+		Some(OpsEnum::External(command)) => {
+			debug!("Entered external operation.");
+
+			match core::external(command) {
+				Ok(status) => {
+					debug!("External operation passed.");
+					process::exit(status.code().unwrap_or(1));
+				}
+				Err(e) => {
+					debug!("External operation failed.\n{}", e);
+					error!("Error occured while forwarding to plugin.\n{}", e);
+				}
+			}
+		}
 		None => {
 			match core::runsplash() {
 				Ok(_) => {
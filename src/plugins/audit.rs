@@ -0,0 +1,189 @@
+//! Plugin Audit Log
+//!
+//! Records every plugin run as a JSON line under `logs/` in the working
+//! directory: plugin, arguments, glyph, start and end times, exit status and
+//! the tail of its output. The log rotates once it grows past its size
+//! limit, keeping a few older files. Known secret values are redacted and
+//! the log is only readable by its owner. Nothing is recorded in headless
+//! mode.
+use crate::errors::PluginError;
+use crate::glyph::Glyph;
+use crate::plugins::command::Placeholders;
+use crate::plugins::PluginConfig;
+use crate::secrets;
+use crate::settings;
+use chrono::{DateTime, Local};
+use getter_derive::Getter;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+/// Audit log file name.
+pub const LOG_FILE: &str = "plugins.log";
+
+/// Size past which the log rotates, in bytes.
+pub const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+/// Rotated log files kept.
+pub const ROTATIONS: usize = 3;
+
+/// Output kept per stream, in bytes.
+pub const MAX_OUTPUT: usize = 4096;
+
+/// Recorded plugin run.
+#[derive(Clone, Debug, Deserialize, Serialize, Getter)]
+#[serde(rename_all = "snake_case")]
+pub struct Entry {
+	plugin: String,
+	args: Vec<String>,
+	glyph: Option<Glyph>,
+	started: DateTime<Local>,
+	ended: DateTime<Local>,
+	status: Option<i32>,
+	success: bool,
+	error: Option<String>,
+	stdout: String,
+	stderr: String,
+}
+
+impl Entry {
+	/// Starts an entry for a plugin run.
+	pub fn start(plugin: &PluginConfig, placeholders: &Placeholders) -> Self {
+		let now = Local::now();
+
+		Entry {
+			plugin: plugin.get_name(),
+			args: placeholders.args.clone(),
+			glyph: placeholders.glyph.clone(),
+			started: now,
+			ended: now,
+			status: None,
+			success: false,
+			error: None,
+			stdout: String::new(),
+			stderr: String::new(),
+		}
+	}
+
+	/// Completes an entry with the outcome of the run, redacting known
+	/// secret values. Protocol calls have no exit status of their own.
+	pub fn finish(
+		mut self,
+		status: Option<i32>,
+		stdout: &[u8],
+		stderr: &[u8],
+		error: Option<&PluginError>,
+	) -> Self {
+		let secrets = secrets::known_values();
+		let redact = |text: &str| secrets::redact(text, &secrets);
+
+		self.ended = Local::now();
+		self.status = status;
+		self.success = status.map_or(true, |status| status == 0) && error.is_none();
+		self.args = self.args.iter().map(|arg| redact(arg)).collect();
+		self.error = error.map(|error| redact(&error.to_string()));
+		self.stdout = truncate(redact(&String::from_utf8_lossy(stdout)).as_bytes());
+		self.stderr = truncate(redact(&String::from_utf8_lossy(stderr)).as_bytes());
+		self
+	}
+}
+
+/// Keeps the tail of some output.
+fn truncate(output: &[u8]) -> String {
+	let start = output.len().saturating_sub(MAX_OUTPUT);
+	String::from_utf8_lossy(&output[start..]).into_owned()
+}
+
+/// Returns the audit log directory.
+pub fn get_logs_dir() -> PathBuf {
+	settings::get_working_dir().join("logs")
+}
+
+/// Returns an audit log file, rotated ones being numbered.
+fn log_path(rotation: usize) -> PathBuf {
+	match rotation {
+		0 => get_logs_dir().join(LOG_FILE),
+		rotation => get_logs_dir().join(format!("{}.{}", LOG_FILE, rotation)),
+	}
+}
+
+/// Shifts log files once the current one is full.
+fn rotate() -> Result<(), PluginError> {
+	let size = fs::metadata(log_path(0)).map_or(0, |metadata| metadata.len());
+
+	if size < MAX_LOG_SIZE {
+		return Ok(());
+	}
+
+	for rotation in (0..ROTATIONS).rev() {
+		let path = log_path(rotation);
+
+		if path.exists() {
+			fs::rename(path, log_path(rotation + 1))?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Appends an entry to the audit log.
+fn append(entry: &Entry) -> Result<(), PluginError> {
+	fs::create_dir_all(get_logs_dir())?;
+	rotate()?;
+
+	let mut options = OpenOptions::new();
+	options.create(true).append(true);
+
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+		options.mode(0o600);
+
+		// Logs written by earlier versions may be readable by others.
+		if let Ok(metadata) = fs::metadata(log_path(0)) {
+			if metadata.permissions().mode() & 0o077 != 0 {
+				fs::set_permissions(log_path(0), fs::Permissions::from_mode(0o600))?;
+			}
+		}
+	}
+
+	let mut file = options.open(log_path(0))?;
+
+	writeln!(file, "{}", serde_json::to_string(entry)?)?;
+	Ok(())
+}
+
+/// Records an entry. Failing to do so never fails the plugin run.
+pub fn record(entry: &Entry) {
+	if settings::get_settings().map_or(true, |settings| settings.get_headless()) {
+		return;
+	}
+
+	if let Err(e) = append(entry) {
+		warn!("Plugin run of {} couldn't be recorded: {}", entry.plugin, e);
+	}
+}
+
+/// Reads recorded entries, oldest first. Malformed lines are skipped.
+pub fn read_log(failed: bool) -> Result<Vec<Entry>, PluginError> {
+	let mut entries = Vec::new();
+
+	for rotation in (0..=ROTATIONS).rev() {
+		let file = match fs::File::open(log_path(rotation)) {
+			Ok(file) => file,
+			Err(_) => continue,
+		};
+
+		for line in BufReader::new(file).lines() {
+			if let Ok(entry) = serde_json::from_str::<Entry>(&line?) {
+				if !failed || !entry.success {
+					entries.push(entry);
+				}
+			}
+		}
+	}
+
+	Ok(entries)
+}
@@ -0,0 +1,108 @@
+//! Built-in Plugins
+//!
+//! Plugins shipped with Godwit and always registered.
+use crate::daemon::{self, Notification};
+use crate::errors::PluginError;
+use crate::glyph::Glyph;
+use crate::plugins::Plugin;
+use crate::scaffold;
+use crate::statehandler::{self, State, StateGraph};
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Warns about projects whose directory has gone missing.
+#[derive(Clone, Debug, Default)]
+pub struct Sanity;
+
+impl Sanity {
+	/// Warns when a state points to a missing directory.
+	fn check(&self, state: &State) {
+		match state.get_directory() {
+			Some(directory) if directory.is_dir() => (),
+			Some(directory) => warn!(
+				"The directory {} of {} doesn't exist.",
+				directory.display(),
+				state.get_glyph()
+			),
+			None => warn!("No directory is set for {}.", state.get_glyph()),
+		}
+	}
+}
+
+impl Plugin for Sanity {
+	fn name(&self) -> &str {
+		"Sanity"
+	}
+
+	fn on_add(&self, state: &State) -> Result<(), PluginError> {
+		self.check(state);
+		Ok(())
+	}
+
+	fn on_switch(&self, _prev_state: Option<&State>, state: &State) -> Result<(), PluginError> {
+		self.check(state);
+		Ok(())
+	}
+
+	fn on_status(&self, state_graph: &StateGraph) -> Result<(), PluginError> {
+		for state in state_graph.get_states() {
+			self.check(&state);
+		}
+		Ok(())
+	}
+}
+
+/// Keeps the daemon informed of the active project.
+#[derive(Clone, Debug, Default)]
+pub struct DaemonNotifier;
+
+impl DaemonNotifier {
+	/// Notifies the daemon, warning on failure.
+	fn notify(&self, event: &str, state: &State, active: Option<Glyph>) {
+		if let Err(e) = daemon::notify(&Notification::new(event, state, active)) {
+			warn!("The daemon couldn't be notified of {}: {}", event, e);
+		}
+	}
+}
+
+impl Plugin for DaemonNotifier {
+	fn name(&self) -> &str {
+		"DaemonNotifier"
+	}
+
+	fn on_add(&self, state: &State) -> Result<(), PluginError> {
+		let active = statehandler::get_active()
+			.ok()
+			.map(|active| active.get_glyph());
+		self.notify("add", state, active);
+		Ok(())
+	}
+
+	fn on_switch(&self, _prev_state: Option<&State>, state: &State) -> Result<(), PluginError> {
+		self.notify("switch", state, Some(state.get_glyph()));
+		Ok(())
+	}
+
+	fn on_remove(&self, state: &State) -> Result<(), PluginError> {
+		let active = statehandler::get_active()
+			.ok()
+			.map(|active| active.get_glyph());
+		self.notify("remove", state, active);
+		Ok(())
+	}
+}
+
+/// Fallback scaffolder, copying a template through the native scaffolder or
+/// creating an empty project directory.
+pub fn scaffold(glyph: &Glyph, location: &Path, template: Option<&str>) -> Result<(), PluginError> {
+	if let Some(template) = template {
+		scaffold::scaffold(glyph, location, template, BTreeMap::new())?;
+		return Ok(());
+	}
+
+	fs::create_dir_all(location)?;
+	info!("Created {} at {}", glyph, location.display());
+	Ok(())
+}
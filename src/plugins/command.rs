@@ -0,0 +1,122 @@
+//! Exec Templates
+//!
+//! Plugin exec strings are split into words following shell quoting and
+//! escaping rules, without involving a shell. Words may hold `{glyph}` and
+//! `{dir}` placeholders, while a word made of `{args}` alone is replaced by
+//! the call arguments. Arguments are appended when `{args}` is absent.
+use crate::errors::PluginError;
+use crate::glyph::Glyph;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Placeholder of the project glyph.
+pub const GLYPH_PLACEHOLDER: &str = "{glyph}";
+
+/// Placeholder of the project directory.
+pub const DIR_PLACEHOLDER: &str = "{dir}";
+
+/// Placeholder of the call arguments.
+pub const ARGS_PLACEHOLDER: &str = "{args}";
+
+/// Values substituted in exec templates.
+#[derive(Clone, Debug, Default)]
+pub struct Placeholders {
+	pub glyph: Option<Glyph>,
+	pub dir: Option<PathBuf>,
+	pub args: Vec<String>,
+}
+
+impl Placeholders {
+	/// Returns placeholders holding call arguments alone.
+	pub fn from_args<I, S>(args: I) -> Self
+	where
+		I: IntoIterator<Item = S>,
+		S: Into<String>,
+	{
+		Placeholders {
+			args: args.into_iter().map(Into::into).collect(),
+			..Default::default()
+		}
+	}
+}
+
+/// Splits an exec string into words.
+pub fn parse(plugin: &str, exec: &str) -> Result<Vec<String>, PluginError> {
+	let words = shell_words::split(exec).map_err(|e| PluginError::MalformedExec {
+		plugin: plugin.to_string(),
+		exec: exec.to_string(),
+		message: e.to_string(),
+	})?;
+
+	if words.is_empty() {
+		return Err(PluginError::MalformedExec {
+			plugin: plugin.to_string(),
+			exec: exec.to_string(),
+			message: String::from("no command is given"),
+		});
+	}
+
+	Ok(words)
+}
+
+/// Expands an exec string into a program followed by its arguments.
+pub fn expand(
+	plugin: &str,
+	exec: &str,
+	placeholders: &Placeholders,
+) -> Result<Vec<String>, PluginError> {
+	let glyph = placeholders
+		.glyph
+		.as_ref()
+		.map(Glyph::to_string)
+		.unwrap_or_default();
+	let dir = placeholders
+		.dir
+		.as_ref()
+		.map(|dir| dir.to_string_lossy().into_owned())
+		.unwrap_or_default();
+
+	let words = parse(plugin, exec)?;
+	let has_args = words.iter().any(|word| word == ARGS_PLACEHOLDER);
+
+	let mut expanded = Vec::new();
+
+	for word in words {
+		if word == ARGS_PLACEHOLDER {
+			expanded.extend(placeholders.args.iter().cloned());
+		} else {
+			expanded.push(
+				word.replace(GLYPH_PLACEHOLDER, &glyph)
+					.replace(DIR_PLACEHOLDER, &dir),
+			);
+		}
+	}
+
+	if !has_args {
+		expanded.extend(placeholders.args.iter().cloned());
+	}
+
+	if expanded.is_empty() {
+		return Err(PluginError::MalformedExec {
+			plugin: plugin.to_string(),
+			exec: exec.to_string(),
+			message: String::from("no command is given"),
+		});
+	}
+
+	Ok(expanded)
+}
+
+/// Builds the command of an exec string.
+pub fn command(
+	plugin: &str,
+	exec: &str,
+	placeholders: &Placeholders,
+) -> Result<Command, PluginError> {
+	let words = expand(plugin, exec, placeholders)?;
+
+	let mut command = Command::new(&words[0]);
+	command.args(&words[1..]);
+
+	Ok(command)
+}
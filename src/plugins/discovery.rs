@@ -0,0 +1,106 @@
+//! Plugin Discovery
+//!
+//! Finds executables named `godwit-<name>` under `plugins/` in the working
+//! directory, then on `PATH`, and exposes them as plugins named `<name>`
+//! without registration. The first executable found for a name wins, and
+//! configured plugins shadow discovered ones.
+use crate::daemon;
+use crate::plugins::{self, PluginConfig};
+use crate::settings;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File name prefix of discoverable plugin executables.
+pub const PREFIX: &str = "godwit-";
+
+/// Returns the directory holding dropped-in plugins.
+pub fn get_plugins_dir() -> PathBuf {
+	settings::get_working_dir().join("plugins")
+}
+
+/// Returns directories searched for plugins, in precedence order.
+fn search_dirs() -> Vec<PathBuf> {
+	let mut dirs = vec![get_plugins_dir()];
+
+	if let Some(paths) = env::var_os("PATH") {
+		dirs.extend(env::split_paths(&paths));
+	}
+
+	dirs
+}
+
+/// Checks whether a path is an executable file.
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+	use std::os::unix::fs::PermissionsExt;
+
+	fs::metadata(path)
+		.map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+		.unwrap_or(false)
+}
+
+/// Checks whether a path is an executable file.
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+	path.is_file()
+}
+
+/// Returns the plugin name of a discoverable executable. The daemon isn't a
+/// plugin.
+fn plugin_name(path: &Path) -> Option<String> {
+	let file_name = if cfg!(windows) {
+		path.file_stem()
+	} else {
+		path.file_name()
+	}?;
+
+	file_name
+		.to_str()
+		.filter(|file_name| *file_name != daemon::DAEMON_EXEC)
+		.and_then(|file_name| file_name.strip_prefix(PREFIX))
+		.filter(|name| !name.is_empty())
+		.map(String::from)
+}
+
+/// Lists discovered plugins.
+pub fn discover() -> Vec<PluginConfig> {
+	let mut discovered: Vec<PluginConfig> = Vec::new();
+
+	for dir in search_dirs() {
+		let entries = match fs::read_dir(&dir) {
+			Ok(entries) => entries,
+			Err(_) => continue,
+		};
+
+		for path in entries
+			.filter_map(|entry| entry.ok())
+			.map(|entry| entry.path())
+		{
+			let name = match plugin_name(&path) {
+				Some(name) => name,
+				None => continue,
+			};
+
+			if is_executable(&path) && !discovered.iter().any(|plugin| plugin.get_name() == name) {
+				discovered.push(plugins::new(
+					&name,
+					&shell_words::quote(&path.to_string_lossy()),
+				));
+			}
+		}
+	}
+
+	discovered
+}
+
+/// Finds a discovered plugin by name.
+pub fn find(name: &str) -> Option<PluginConfig> {
+	let file_name = format!("{}{}{}", PREFIX, name, env::consts::EXE_SUFFIX);
+
+	search_dirs()
+		.into_iter()
+		.map(|dir| dir.join(&file_name))
+		.find(|path| is_executable(path))
+		.map(|path| plugins::new(name, &shell_words::quote(&path.to_string_lossy())))
+}
@@ -0,0 +1,132 @@
+//! Executable Plugins
+//!
+//! Subprocess adapter exposing configured plugin executables through the
+//! `Plugin` trait. An executable only hears of the hooks it subscribes to in
+//! its configuration. Protocol plugins get the hook as a call over stdio,
+//! others are called with the hook name as argument and receive the event
+//! context through `GODWIT_*` environment variables. Hook actions naming a
+//! plugin call it the same way.
+use crate::errors::PluginError;
+use crate::plugins::command::Placeholders;
+use crate::plugins::protocol::{self, Context};
+use crate::plugins::{process, Event, Plugin, PluginConfig};
+use crate::statehandler::State;
+use std::collections::BTreeMap;
+
+/// Subprocess-backed plugin.
+#[derive(Clone, Debug)]
+pub struct ExecPlugin {
+	config: PluginConfig,
+}
+
+impl ExecPlugin {
+	/// Returns new adapter for a configured plugin.
+	pub fn new(config: PluginConfig) -> Self {
+		ExecPlugin { config: config }
+	}
+}
+
+/// Exposes a state to plugin executables under a prefix.
+fn state_vars(prefix: &str, state: &State, vars: &mut BTreeMap<String, String>) {
+	vars.insert(format!("{}GLYPH", prefix), state.get_glyph().to_string());
+	vars.insert(
+		format!("{}DIR", prefix),
+		state
+			.get_directory()
+			.unwrap_or_default()
+			.to_string_lossy()
+			.into_owned(),
+	);
+}
+
+/// Returns environment variables describing an event.
+fn event_vars(event: &Event) -> BTreeMap<String, String> {
+	let mut vars = BTreeMap::new();
+
+	vars.insert(String::from("GODWIT_HOOK"), event.hook().to_string());
+
+	match *event {
+		Event::Init(settings) => {
+			vars.insert(
+				String::from("GODWIT_WORKING_DIR"),
+				settings
+					.get_working_dir()
+					.unwrap_or_default()
+					.to_string_lossy()
+					.into_owned(),
+			);
+		}
+		Event::Add(state) | Event::Remove(state) => state_vars("GODWIT_", state, &mut vars),
+		Event::Switch(prev_state, state) => {
+			state_vars("GODWIT_", state, &mut vars);
+
+			if let Some(prev_state) = prev_state {
+				state_vars("GODWIT_PREV_", prev_state, &mut vars);
+			}
+		}
+		Event::Status(state_graph) => {
+			if let Some(active) = state_graph.get_active() {
+				state_vars("GODWIT_", &active, &mut vars);
+			}
+		}
+	}
+
+	vars
+}
+
+impl ExecPlugin {
+	/// Calls the plugin on a hook, whether subscribed or not. Variables are
+	/// only passed to plugins outside of the protocol.
+	pub fn call_hook(
+		&self,
+		hook: &str,
+		context: Context,
+		vars: BTreeMap<String, String>,
+	) -> Result<(), PluginError> {
+		if self.config.protocol {
+			protocol::call_once(&self.config, hook, context, Vec::new())?;
+			return Ok(());
+		}
+
+		let placeholders = Placeholders {
+			glyph: context.glyph,
+			dir: context.directory,
+			args: vec![hook.to_string()],
+		};
+
+		let mut command = self.config.command(&placeholders)?;
+		command.envs(vars);
+
+		let output = process::run(&self.config, command, &placeholders)?;
+
+		if output.status.success() {
+			Ok(())
+		} else {
+			Err(PluginError::HookFailed {
+				plugin: self.config.name.clone(),
+				hook: hook.to_string(),
+				message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+			})
+		}
+	}
+}
+
+impl Plugin for ExecPlugin {
+	fn name(&self) -> &str {
+		&self.config.name
+	}
+
+	fn on_event(&self, event: &Event) -> Result<(), PluginError> {
+		let hook = event.hook();
+
+		if !self.config.hooks.contains(&hook) {
+			return Ok(());
+		}
+
+		self.call_hook(
+			&hook.to_string(),
+			Context::from_event(event)?,
+			event_vars(event),
+		)
+	}
+}
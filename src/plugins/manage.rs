@@ -0,0 +1,189 @@
+//! Plugin Management
+//!
+//! Adds, toggles and removes configured plugins in the user settings, and
+//! checks their health. A plugin is healthy when its executable is found
+//! and, for protocol plugins checked on demand, when it completes a
+//! handshake.
+use crate::errors::PluginError;
+use crate::plugins::command::{self, Placeholders};
+use crate::plugins::process;
+use crate::plugins::protocol::Session;
+use crate::plugins::{self, PluginConfig};
+use crate::settings;
+use crate::statehandler::{self, State};
+use serde_json::Value;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Health of a configured plugin.
+#[derive(Clone, Debug)]
+pub struct Health {
+	/// Resolved executable, if found.
+	pub exec: Option<PathBuf>,
+	/// Negotiated protocol version, or the reason none was, for checked
+	/// protocol plugins.
+	pub protocol: Option<Result<u32, String>>,
+}
+
+impl Health {
+	/// Checks whether the plugin is usable.
+	pub fn is_healthy(&self) -> bool {
+		self.exec.is_some()
+			&& self
+				.protocol
+				.as_ref()
+				.map_or(true, |protocol| protocol.is_ok())
+	}
+}
+
+/// Resolves a command as a path or by searching `PATH`.
+pub fn find_executable(command: &str) -> Option<PathBuf> {
+	let path = Path::new(command);
+
+	if path.components().count() > 1 {
+		return Some(path.to_path_buf()).filter(|path| path.is_file());
+	}
+
+	env::var_os("PATH").and_then(|paths| {
+		env::split_paths(&paths)
+			.flat_map(|dir| {
+				let candidate = dir.join(command);
+				vec![candidate.clone(), candidate.with_extension("exe")]
+			})
+			.find(|candidate| candidate.is_file())
+	})
+}
+
+/// Checks health of a plugin. When checking, protocol plugins are started
+/// for a handshake, otherwise nothing is run.
+pub fn health(config: &PluginConfig, check: bool) -> Health {
+	let exec_path = command::parse(&config.get_name(), &config.get_exec())
+		.ok()
+		.and_then(|words| find_executable(&words[0]));
+
+	let protocol = if check && config.get_protocol() && exec_path.is_some() && config.get_enabled()
+	{
+		Some(
+			Session::start(config, &Placeholders::default())
+				.and_then(|session| {
+					let version = session.version();
+					session.close().map(|_| version)
+				})
+				.map_err(|e| e.to_string()),
+		)
+	} else {
+		None
+	};
+
+	Health {
+		exec: exec_path,
+		protocol: protocol,
+	}
+}
+
+/// Lists configured and discovered plugins along with their health,
+/// handshaking with protocol plugins when checking.
+pub fn list_plugins(check: bool) -> Result<Vec<(PluginConfig, Health)>, PluginError> {
+	Ok(plugins::get_plugins()?
+		.into_iter()
+		.map(|plugin| {
+			let health = health(&plugin, check);
+			(plugin, health)
+		})
+		.collect())
+}
+
+/// Adds a plugin to user settings. Names must be unique among configured
+/// plugins, and shadow discovered ones.
+pub fn add_plugin(plugin: PluginConfig) -> Result<(), PluginError> {
+	command::parse(&plugin.get_name(), &plugin.get_exec())?;
+
+	if settings::get_settings()?
+		.get_plugins()
+		.iter()
+		.any(|s_plugin| s_plugin.get_name() == plugin.get_name())
+	{
+		return Err(PluginError::PluginExists {
+			plugin: plugin.get_name(),
+		});
+	}
+
+	let mut plugins = settings::get_user_settings()?.get_plugins();
+	plugins.push(plugin);
+
+	settings::save_plugins(plugins)?;
+	Ok(())
+}
+
+/// Applies a change to a plugin of the user settings.
+fn update_plugin<F>(name: &str, update: F) -> Result<(), PluginError>
+where
+	F: FnOnce(&mut Vec<PluginConfig>, usize),
+{
+	let mut plugins = settings::get_user_settings()?.get_plugins();
+
+	let position = plugins
+		.iter()
+		.position(|plugin| plugin.get_name() == name)
+		.ok_or_else(|| PluginError::PluginNotFound {
+			plugin: name.to_string(),
+		})?;
+
+	update(&mut plugins, position);
+
+	settings::save_plugins(plugins)?;
+	Ok(())
+}
+
+/// Enables or disables a plugin of the user settings.
+pub fn set_enabled(name: &str, enabled: bool) -> Result<(), PluginError> {
+	update_plugin(name, |plugins, position| {
+		plugins[position].enabled(enabled);
+	})
+}
+
+/// Removes a plugin from user settings.
+pub fn remove_plugin(name: &str) -> Result<(), PluginError> {
+	update_plugin(name, |plugins, position| {
+		plugins.remove(position);
+	})
+}
+
+/// Calls a plugin directly. Protocol plugins take the method as first
+/// argument and return its result, others run with the arguments in place
+/// of `{args}`.
+pub fn call(name: &str, mut args: Vec<String>) -> Result<Option<Value>, PluginError> {
+	let plugin = plugins::get_plugin(name)?;
+
+	if plugin.get_protocol() {
+		if args.is_empty() {
+			return Err(PluginError::Protocol {
+				plugin: plugin.get_name(),
+				message: String::from("a method to call is needed"),
+			});
+		}
+
+		let method = args.remove(0);
+		return plugins::bind(name, &method, args).map(Some);
+	}
+
+	let active = statehandler::get_active().ok();
+
+	let placeholders = Placeholders {
+		glyph: active.as_ref().map(State::get_glyph),
+		dir: active.as_ref().and_then(State::get_directory),
+		args: args,
+	};
+
+	let status = process::run(&plugin, plugin.command(&placeholders)?, &placeholders)?.status;
+
+	if !status.success() {
+		return Err(PluginError::HookFailed {
+			plugin: plugin.get_name(),
+			hook: String::from("call"),
+			message: status.to_string(),
+		});
+	}
+
+	Ok(None)
+}
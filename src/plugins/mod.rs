@@ -2,86 +2,288 @@
 //!
 //! A composite processor for all godwit-compatible plugins.
 //! Must follow a unified standard to keep minimal deviation.
+//!
+//! Plugins implement the `Plugin` lifecycle trait. Built-in plugins and
+//! plugins registered in-process by library users run as Rust code, while
+//! configured executables are wrapped by subprocess adapters.
+pub mod audit;
+pub mod builtin;
+pub mod command;
+pub mod discovery;
+pub mod exec;
+pub mod manage;
+pub mod process;
+pub mod protocol;
+pub mod registry;
+pub mod weaver;
+
+pub use crate::plugins::registry::{register, registry, unregister, Registry};
+pub use crate::plugins::weaver::weave;
+
 use crate::errors::PluginError;
-use crate::settings;
+use crate::plugins::command::Placeholders;
+use crate::plugins::protocol::Context;
+use crate::settings::{self, Settings};
+use crate::statehandler::{self, State, StateGraph};
 use getter_derive::Getter;
 use serde::{Deserialize, Serialize};
-use std::process::{Command, Output, Stdio};
+use serde_json::Value;
+use std::fmt;
+use std::process::{Command, Output};
+use std::str::FromStr;
+
+/// Lifecycle hooks fired by core operations.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Hook {
+	Init,
+	Add,
+	Switch,
+	Remove,
+	Status,
+}
+
+impl Hook {
+	pub fn iterator() -> impl Iterator<Item = Hook> {
+		[
+			Hook::Init,
+			Hook::Add,
+			Hook::Switch,
+			Hook::Remove,
+			Hook::Status,
+		]
+		.iter()
+		.copied()
+	}
+}
+
+impl fmt::Display for Hook {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Hook::Init => write!(f, "init"),
+			Hook::Add => write!(f, "add"),
+			Hook::Switch => write!(f, "switch"),
+			Hook::Remove => write!(f, "remove"),
+			Hook::Status => write!(f, "status"),
+		}
+	}
+}
+
+impl FromStr for Hook {
+	type Err = PluginError;
+
+	fn from_str(hook_str: &str) -> Result<Self, Self::Err> {
+		Hook::iterator()
+			.find(|hook| hook.to_string() == hook_str.trim().to_lowercase())
+			.ok_or_else(|| PluginError::UnknownHook {
+				hook: hook_str.into(),
+			})
+	}
+}
+
+/// Lifecycle event along with its context.
+#[derive(Clone, Debug)]
+pub enum Event<'a> {
+	Init(&'a Settings),
+	Add(&'a State),
+	Switch(Option<&'a State>, &'a State),
+	Remove(&'a State),
+	Status(&'a StateGraph),
+}
+
+impl<'a> Event<'a> {
+	/// Returns the hook an event fires.
+	pub fn hook(&self) -> Hook {
+		match self {
+			Event::Init(_) => Hook::Init,
+			Event::Add(_) => Hook::Add,
+			Event::Switch(..) => Hook::Switch,
+			Event::Remove(_) => Hook::Remove,
+			Event::Status(_) => Hook::Status,
+		}
+	}
+}
+
+/// Lifecycle interface for Godwit plugins. Every hook defaults to a no-op.
+pub trait Plugin: Send + Sync {
+	/// Returns the unique plugin name.
+	fn name(&self) -> &str;
+
+	/// Called once Godwit is set up.
+	fn on_init(&self, _settings: &Settings) -> Result<(), PluginError> {
+		Ok(())
+	}
+
+	/// Called once a project is added.
+	fn on_add(&self, _state: &State) -> Result<(), PluginError> {
+		Ok(())
+	}
 
-// TODO: Convert this into a trait
+	/// Called once a project becomes active.
+	fn on_switch(&self, _prev_state: Option<&State>, _state: &State) -> Result<(), PluginError> {
+		Ok(())
+	}
+
+	/// Called once a project is removed.
+	fn on_remove(&self, _state: &State) -> Result<(), PluginError> {
+		Ok(())
+	}
+
+	/// Called when projects are listed.
+	fn on_status(&self, _state_graph: &StateGraph) -> Result<(), PluginError> {
+		Ok(())
+	}
+
+	/// Dispatches an event to its hook.
+	fn on_event(&self, event: &Event) -> Result<(), PluginError> {
+		match *event {
+			Event::Init(settings) => self.on_init(settings),
+			Event::Add(state) => self.on_add(state),
+			Event::Switch(prev_state, state) => self.on_switch(prev_state, state),
+			Event::Remove(state) => self.on_remove(state),
+			Event::Status(state_graph) => self.on_status(state_graph),
+		}
+	}
+}
 
-/// Plugin structure
+/// Configured executable plugin.
 #[derive(Clone, Debug, Deserialize, Serialize, Getter)]
 #[serde(rename_all = "snake_case")]
-pub struct Plugin {
+pub struct PluginConfig {
 	name: String,
 	exec: String,
+	#[serde(default)]
+	hooks: Vec<Hook>,
+	#[serde(default)]
+	protocol: bool,
+	#[serde(default = "enabled_default")]
+	enabled: bool,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	timeout: Option<u64>,
+}
+
+impl PluginConfig {
+	/// Sets lifecycle hooks the plugin subscribes to.
+	pub fn hooks(&mut self, hooks: Vec<Hook>) -> &mut Self {
+		self.hooks = hooks;
+		self
+	}
+
+	/// Sets whether the plugin speaks the stdio protocol.
+	pub fn protocol(&mut self, protocol: bool) -> &mut Self {
+		self.protocol = protocol;
+		self
+	}
+
+	/// Sets whether the plugin is used at all.
+	pub fn enabled(&mut self, enabled: bool) -> &mut Self {
+		self.enabled = enabled;
+		self
+	}
+
+	/// Sets seconds the plugin may run for, without limit if unset.
+	pub fn timeout(&mut self, timeout: Option<u64>) -> &mut Self {
+		self.timeout = timeout;
+		self
+	}
+
+	/// Builds the command running the plugin from its exec template.
+	pub fn command(&self, placeholders: &Placeholders) -> Result<Command, PluginError> {
+		command::command(&self.name, &self.exec, placeholders)
+	}
 }
 
-impl Default for Plugin {
+impl Default for PluginConfig {
 	fn default() -> Self {
-		Plugin {
+		PluginConfig {
 			name: Default::default(),
 			exec: Default::default(),
+			hooks: Default::default(),
+			protocol: false,
+			enabled: true,
+			timeout: None,
 		}
 	}
 }
 
+/// Plugins are enabled unless configured otherwise.
+fn enabled_default() -> bool {
+	true
+}
+
 /// Call a detached external process for a plugin.
 pub fn invoke(plugin_str: &str, args_options: Option<Vec<&str>>) -> Result<Output, PluginError> {
-	let command_str = get_plugin(plugin_str)?.get_exec();
-	let mut args = args_options.unwrap_or_default();
-
-	let command = str_to_command!(command_str, &mut args);
+	let args = args_options.unwrap_or_default();
 
-	let output = Command::new(command).args(args).output()?;
+	let plugin = get_plugin(plugin_str)?;
+	let placeholders = Placeholders::from_args(args);
+	let captured = process::run(&plugin, plugin.command(&placeholders)?, &placeholders)?;
 
-	Ok(output)
+	Ok(Output {
+		status: captured.status,
+		stdout: captured.stdout,
+		stderr: captured.stderr,
+	})
 }
 
-/// Bind stdio to spawned child process and pipe io to it.
-pub fn bind(plugin_str: &str, args_options: Option<Vec<&str>>) -> Result<Vec<u8>, PluginError> {
-	let command_str = get_plugin(plugin_str)?.get_exec();
-	let mut args = args_options.unwrap_or_default();
+/// Call a method of a protocol plugin over its stdio, in the context of the
+/// active project.
+pub fn bind(plugin_str: &str, method: &str, args: Vec<String>) -> Result<Value, PluginError> {
+	let plugin = get_plugin(plugin_str)?;
 
-	let command = str_to_command!(command_str, &mut args);
+	if !plugin.protocol {
+		return Err(PluginError::Protocol {
+			plugin: plugin.name,
+			message: String::from("it isn't configured as a protocol plugin"),
+		});
+	}
 
-	let child = Command::new(command)
-		.args(args)
-		.stdin(Stdio::piped())
-		.stderr(Stdio::piped())
-		.stdout(Stdio::piped())
-		.spawn()?;
+	let active = statehandler::get_active().ok();
 
-	let output = child.wait_with_output()?;
+	protocol::call_once(&plugin, method, Context::from_state(active.as_ref())?, args)
+}
 
-	if output.status.success() {
-		Ok(output.stdout)
-	} else {
-		Err(PluginError::StdErr {
-			message: String::from_utf8(output.stderr)?,
-		})
+/// Returns configured plugins followed by discovered ones they don't shadow.
+pub fn get_plugins() -> Result<Vec<PluginConfig>, PluginError> {
+	let mut plugins = settings::get_settings()?.get_plugins();
+
+	for plugin in discovery::discover() {
+		if !plugins.iter().any(|c_plugin| c_plugin.name == plugin.name) {
+			plugins.push(plugin);
+		}
 	}
+
+	Ok(plugins)
 }
 
-/// Retrieve plugin from queried name.
-pub fn get_plugin(q_plugin: &str) -> Result<Plugin, PluginError> {
+/// Retrieve enabled plugin from queried name, configured or discovered.
+pub fn get_plugin(q_plugin: &str) -> Result<PluginConfig, PluginError> {
 	let plugins = settings::get_settings()?.get_plugins();
 	plugins
 		.into_iter()
 		.find(|plugin| plugin.name == q_plugin)
+		.or_else(|| discovery::find(q_plugin))
 		.map_or(
 			Err(PluginError::PluginNotFound {
 				plugin: q_plugin.into(),
 			}),
-			|plugin| Ok(plugin),
+			|plugin| {
+				if plugin.enabled {
+					Ok(plugin)
+				} else {
+					Err(PluginError::PluginDisabled {
+						plugin: plugin.name,
+					})
+				}
+			},
 		)
 }
 
 /// Create new plugin.
-pub fn new(name: &str, exec: &str) -> Plugin {
-	Plugin {
+pub fn new(name: &str, exec: &str) -> PluginConfig {
+	PluginConfig {
 		name: name.to_string(),
 		exec: exec.to_string(),
+		..Default::default()
 	}
 }
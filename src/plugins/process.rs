@@ -0,0 +1,433 @@
+//! Plugin Processes
+//!
+//! Runs plugin processes within their configured timeout. Output is streamed
+//! to the output sink, the terminal unless replaced, as it comes while being
+//! captured. In export mode, plugin stdout goes to stderr. Every run is
+//! recorded in the audit log.
+//!
+//! Plugins run in a process group of their own, which is ended as a whole,
+//! along with whatever the plugin started. A plugin reading from the
+//! terminal is handed it while running, so that Ctrl-C reaches the plugin
+//! alone. Otherwise Ctrl-C ends the running plugin instead of Godwit.
+//! Whatever a plugin leaves running once it exits is ended as well if it
+//! keeps the plugin output open.
+use crate::env;
+use crate::errors::PluginError;
+use crate::plugins::audit::{self, Entry};
+use crate::plugins::command::Placeholders;
+use crate::plugins::PluginConfig;
+use log::{debug, warn};
+use std::io::{self, Read, Write};
+use std::process::{self, Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Once, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Interval at which running plugins are polled.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Time given to an interrupted plugin to exit once terminated before it is
+/// killed.
+pub const INTERRUPT_GRACE: Duration = Duration::from_secs(2);
+
+/// Time given to the output of an exited plugin to close before whatever
+/// still holds it open is killed.
+pub const OUTPUT_GRACE: Duration = Duration::from_secs(1);
+
+/// Output stream of a plugin.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Stream {
+	Stdout,
+	Stderr,
+}
+
+/// Receiver of streamed plugin output.
+pub type Sink = Arc<dyn Fn(&str, Stream, &[u8]) + Send + Sync>;
+
+/// Sink replacing the terminal, such as a TUI log pane.
+static SINK: RwLock<Option<Sink>> = RwLock::new(None);
+
+/// Set once Ctrl-C is hit while a plugin runs.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Number of plugins running.
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+/// Guards the Ctrl-C handler installation.
+static HANDLER: Once = Once::new();
+
+/// Routes plugin output to a sink instead of the terminal. The sink is given
+/// the plugin name, the stream and a chunk of output.
+pub fn set_output_sink<F>(sink: F)
+where
+	F: Fn(&str, Stream, &[u8]) + Send + Sync + 'static,
+{
+	if let Ok(mut current) = SINK.write() {
+		*current = Some(Arc::new(sink));
+	}
+}
+
+/// Routes plugin output back to the terminal.
+pub fn reset_output_sink() {
+	if let Ok(mut current) = SINK.write() {
+		*current = None;
+	}
+}
+
+/// Passes a chunk of output to the sink.
+fn emit(plugin: &str, stream: Stream, chunk: &[u8]) {
+	if let Some(sink) = SINK.read().ok().and_then(|sink| sink.clone()) {
+		return sink(plugin, stream, chunk);
+	}
+
+	let _ = match stream {
+		Stream::Stdout if !env::is_export_only() => {
+			io::stdout().write_all(chunk).and(io::stdout().flush())
+		}
+		Stream::Stdout => io::stderr().write_all(chunk).and(io::stderr().flush()),
+		Stream::Stderr => io::stderr().write_all(chunk).and(io::stderr().flush()),
+	};
+}
+
+/// Installs the Ctrl-C handler. Ctrl-C outside of plugin runs still exits.
+fn watch_interrupts() {
+	HANDLER.call_once(|| {
+		let result = ctrlc::set_handler(|| {
+			if RUNNING.load(Ordering::SeqCst) == 0 {
+				process::exit(130);
+			}
+			INTERRUPTED.store(true, Ordering::SeqCst);
+		});
+
+		if let Err(e) = result {
+			warn!("Ctrl-C won't end running plugins: {}", e);
+		}
+	});
+}
+
+/// Marks a plugin running for as long as it lives.
+pub struct Running;
+
+impl Running {
+	/// Marks a plugin running.
+	pub fn start() -> Self {
+		watch_interrupts();
+		INTERRUPTED.store(false, Ordering::SeqCst);
+		RUNNING.fetch_add(1, Ordering::SeqCst);
+		Running
+	}
+}
+
+impl Drop for Running {
+	fn drop(&mut self) {
+		RUNNING.fetch_sub(1, Ordering::SeqCst);
+	}
+}
+
+/// Returns the time a plugin may run until.
+pub fn deadline(plugin: &PluginConfig, started: Instant) -> Option<Instant> {
+	plugin
+		.get_timeout()
+		.map(|timeout| started + Duration::from_secs(timeout))
+}
+
+/// Fails once Ctrl-C is hit or the deadline passes, ending the plugin.
+pub fn check(
+	plugin: &PluginConfig,
+	child: &mut Child,
+	deadline: Option<Instant>,
+) -> Result<(), PluginError> {
+	if INTERRUPTED.swap(false, Ordering::SeqCst) {
+		debug!("Ending interrupted {}.", plugin.get_name());
+		terminate(child);
+		return Err(PluginError::Interrupted {
+			plugin: plugin.get_name(),
+		});
+	}
+
+	if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+		kill(child);
+		return Err(PluginError::Timeout {
+			plugin: plugin.get_name(),
+			seconds: plugin.get_timeout().unwrap_or_default(),
+		});
+	}
+
+	Ok(())
+}
+
+/// Puts a command in a process group of its own once spawned.
+pub fn isolate(command: &mut Command) -> &mut Command {
+	#[cfg(unix)]
+	unsafe {
+		use std::os::unix::process::CommandExt;
+
+		command.pre_exec(|| {
+			if libc::setpgid(0, 0) != 0 {
+				return Err(io::Error::last_os_error());
+			}
+			Ok(())
+		});
+	}
+
+	command
+}
+
+/// Signals the process group of a plugin.
+#[cfg(unix)]
+fn signal(child: &Child, signal: libc::c_int) {
+	unsafe {
+		libc::kill(-(child.id() as libc::pid_t), signal);
+	}
+}
+
+/// Kills a plugin along with its process group.
+pub fn kill(child: &mut Child) {
+	#[cfg(unix)]
+	signal(child, libc::SIGKILL);
+
+	let _ = child.kill();
+	let _ = child.wait();
+}
+
+/// Terminates a plugin along with its process group, killing them after a
+/// grace period.
+fn terminate(child: &mut Child) {
+	#[cfg(unix)]
+	signal(child, libc::SIGTERM);
+
+	let grace = Instant::now() + INTERRUPT_GRACE;
+
+	while Instant::now() < grace {
+		if let Ok(Some(_)) = child.try_wait() {
+			break;
+		}
+		thread::sleep(POLL_INTERVAL);
+	}
+
+	kill(child);
+}
+
+/// Terminal handed to the process group of a plugin, given back once
+/// dropped.
+struct Terminal {
+	#[cfg(unix)]
+	owner: libc::pid_t,
+}
+
+impl Terminal {
+	/// Hands the terminal to the process group of a plugin, if Godwit holds
+	/// it.
+	#[cfg(unix)]
+	fn hand(child: &Child) -> Option<Self> {
+		unsafe {
+			let owner = libc::getpgrp();
+
+			if libc::isatty(libc::STDIN_FILENO) == 0 || libc::tcgetpgrp(libc::STDIN_FILENO) != owner
+			{
+				return None;
+			}
+
+			let group = child.id() as libc::pid_t;
+			libc::setpgid(group, group);
+
+			if set_foreground(group) {
+				// The plugin may have been stopped reading before being handed
+				// the terminal.
+				signal(child, libc::SIGCONT);
+				Some(Terminal { owner: owner })
+			} else {
+				None
+			}
+		}
+	}
+
+	#[cfg(not(unix))]
+	fn hand(_child: &Child) -> Option<Self> {
+		None
+	}
+}
+
+impl Drop for Terminal {
+	fn drop(&mut self) {
+		#[cfg(unix)]
+		set_foreground(self.owner);
+	}
+}
+
+/// Makes a process group the foreground one of the terminal. Background
+/// groups may only do so while ignoring SIGTTOU.
+#[cfg(unix)]
+fn set_foreground(group: libc::pid_t) -> bool {
+	unsafe {
+		let handler = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+		let result = libc::tcsetpgrp(libc::STDIN_FILENO, group);
+		libc::signal(libc::SIGTTOU, handler);
+		result == 0
+	}
+}
+
+/// Checks whether a plugin ended on Ctrl-C.
+fn interrupted(status: &ExitStatus) -> bool {
+	#[cfg(unix)]
+	{
+		use std::os::unix::process::ExitStatusExt;
+
+		status.signal() == Some(libc::SIGINT)
+	}
+
+	#[cfg(not(unix))]
+	{
+		let _ = status;
+		false
+	}
+}
+
+/// Waits for a plugin to exit within its deadline.
+pub fn wait(
+	plugin: &PluginConfig,
+	child: &mut Child,
+	deadline: Option<Instant>,
+) -> Result<ExitStatus, PluginError> {
+	loop {
+		if let Some(status) = child.try_wait()? {
+			return Ok(status);
+		}
+
+		check(plugin, child, deadline)?;
+		thread::sleep(POLL_INTERVAL);
+	}
+}
+
+/// Streams a plugin pipe to the sink, returning all output once closed.
+pub fn pump<R>(plugin: &str, stream: Stream, mut reader: R) -> JoinHandle<Vec<u8>>
+where
+	R: Read + Send + 'static,
+{
+	let plugin = plugin.to_string();
+
+	thread::spawn(move || {
+		let mut captured = Vec::new();
+		let mut buffer = [0; 8192];
+
+		loop {
+			match reader.read(&mut buffer) {
+				Ok(0) | Err(_) => break,
+				Ok(count) => {
+					emit(&plugin, stream, &buffer[..count]);
+					captured.extend_from_slice(&buffer[..count]);
+				}
+			}
+		}
+
+		captured
+	})
+}
+
+/// Waits up to a grace period for output readers to reach the end of their
+/// pipes. Returns whether they all did.
+fn settle(readers: &[Option<JoinHandle<Vec<u8>>>], grace: Duration) -> bool {
+	let until = Instant::now() + grace;
+
+	loop {
+		let settled = readers.iter().flatten().all(JoinHandle::is_finished);
+
+		if settled || Instant::now() >= until {
+			return settled;
+		}
+		thread::sleep(POLL_INTERVAL);
+	}
+}
+
+/// Output of a finished plugin.
+#[derive(Clone, Debug)]
+pub struct Captured {
+	pub status: ExitStatus,
+	pub stdout: Vec<u8>,
+	pub stderr: Vec<u8>,
+}
+
+/// Runs a plugin command built from placeholders, streaming and capturing
+/// its output. Stdin is left to the terminal. The run is recorded in the
+/// audit log.
+pub fn run(
+	plugin: &PluginConfig,
+	command: Command,
+	placeholders: &Placeholders,
+) -> Result<Captured, PluginError> {
+	let entry = Entry::start(plugin, placeholders);
+
+	let result = spawn(plugin, command);
+
+	audit::record(&match &result {
+		Ok(captured) => entry.finish(
+			Some(captured.status.code().unwrap_or(-1)),
+			&captured.stdout,
+			&captured.stderr,
+			None,
+		),
+		Err(e) => entry.finish(None, &[], &[], Some(e)),
+	});
+
+	result
+}
+
+/// Spawns a plugin command and waits for its output.
+fn spawn(plugin: &PluginConfig, mut command: Command) -> Result<Captured, PluginError> {
+	let _running = Running::start();
+	let deadline = deadline(plugin, Instant::now());
+
+	let mut child = isolate(&mut command)
+		.stdin(Stdio::inherit())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()?;
+
+	let terminal = Terminal::hand(&child);
+
+	let name = plugin.get_name();
+	let stdout = child
+		.stdout
+		.take()
+		.map(|out| pump(&name, Stream::Stdout, out));
+	let stderr = child
+		.stderr
+		.take()
+		.map(|err| pump(&name, Stream::Stderr, err));
+
+	let result = wait(plugin, &mut child, deadline);
+	drop(terminal);
+	let status = result?;
+
+	if interrupted(&status) {
+		return Err(PluginError::Interrupted {
+			plugin: plugin.get_name(),
+		});
+	}
+
+	// Background processes of the plugin may still hold its output open.
+	let readers = [stdout, stderr];
+
+	if !settle(&readers, OUTPUT_GRACE) {
+		debug!("Ending what {} left holding its output.", plugin.get_name());
+		#[cfg(unix)]
+		signal(&child, libc::SIGKILL);
+		settle(&readers, OUTPUT_GRACE);
+	}
+
+	// Readers still blocked on output held outside the process group are
+	// left behind.
+	let [stdout, stderr] = readers.map(|reader| {
+		reader
+			.filter(JoinHandle::is_finished)
+			.and_then(|reader| reader.join().ok())
+			.unwrap_or_default()
+	});
+
+	Ok(Captured {
+		status: status,
+		stdout: stdout,
+		stderr: stderr,
+	})
+}
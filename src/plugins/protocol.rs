@@ -0,0 +1,392 @@
+//! Plugin Protocol
+//!
+//! Versioned JSON-lines protocol spoken over plugin stdio. Godwit opens a
+//! session with a handshake, to which the plugin answers with its version
+//! and capabilities. Calls then carry a method along with the project
+//! context and arguments, and are answered by a result or an error. A
+//! shutdown message ends the session. Stderr is streamed like the output of
+//! plain plugins.
+//!
+//! ```text
+//! > {"type":"handshake","version":1,"godwit":"0.1.1"}
+//! < {"type":"handshake","version":1,"name":"Weaver","capabilities":["add","scaffold"]}
+//! > {"type":"call","id":1,"method":"add","context":{...},"args":[]}
+//! < {"type":"result","id":1,"value":null}
+//! > {"type":"shutdown"}
+//! ```
+use crate::errors::PluginError;
+use crate::glyph::Glyph;
+use crate::plugins::audit::{self, Entry};
+use crate::plugins::command::Placeholders;
+use crate::plugins::process::{self, Running, Stream};
+use crate::plugins::{Event, PluginConfig};
+use crate::settings;
+use crate::statehandler::State;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Instant;
+
+/// Protocol version spoken by this build.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Project context passed along every call.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Context {
+	pub glyph: Option<Glyph>,
+	pub directory: Option<PathBuf>,
+	pub prev_glyph: Option<Glyph>,
+	pub prev_directory: Option<PathBuf>,
+	pub settings: Value,
+}
+
+impl Context {
+	/// Returns context of a state along with effective settings.
+	pub fn from_state(state: Option<&State>) -> Result<Self, PluginError> {
+		Ok(Context {
+			glyph: state.map(State::get_glyph),
+			directory: state.and_then(State::get_directory),
+			settings: serde_json::to_value(settings::get_settings()?)?,
+			..Default::default()
+		})
+	}
+
+	/// Returns context of a lifecycle event.
+	pub fn from_event(event: &Event) -> Result<Self, PluginError> {
+		match *event {
+			Event::Init(settings) => Ok(Context {
+				settings: serde_json::to_value(settings)?,
+				..Default::default()
+			}),
+			Event::Add(state) | Event::Remove(state) => Context::from_state(Some(state)),
+			Event::Switch(prev_state, state) => Ok(Context {
+				prev_glyph: prev_state.map(State::get_glyph),
+				prev_directory: prev_state.and_then(State::get_directory),
+				..Context::from_state(Some(state))?
+			}),
+			Event::Status(state_graph) => Context::from_state(state_graph.get_active().as_ref()),
+		}
+	}
+}
+
+/// Messages sent to plugins. Call contexts are boxed, as they outweigh the
+/// other messages.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+	Handshake {
+		version: u32,
+		godwit: String,
+	},
+	Call {
+		id: u64,
+		method: String,
+		context: Box<Context>,
+		args: Vec<String>,
+	},
+	Shutdown,
+}
+
+/// Messages received from plugins.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+	Handshake {
+		version: u32,
+		name: String,
+		#[serde(default)]
+		capabilities: Vec<String>,
+	},
+	Result {
+		id: u64,
+		#[serde(default)]
+		value: Value,
+	},
+	Error {
+		id: u64,
+		code: String,
+		message: String,
+	},
+}
+
+/// Open protocol session with a plugin process. The whole session runs
+/// within the plugin timeout. A session dropped without being closed ends
+/// the plugin.
+pub struct Session {
+	plugin: String,
+	config: PluginConfig,
+	child: Child,
+	stdin: Option<ChildStdin>,
+	lines: Receiver<io::Result<String>>,
+	deadline: Option<Instant>,
+	version: u32,
+	capabilities: Vec<String>,
+	next_id: u64,
+	_running: Running,
+}
+
+impl Session {
+	/// Spawns a plugin and performs the handshake.
+	pub fn start(config: &PluginConfig, placeholders: &Placeholders) -> Result<Self, PluginError> {
+		let running = Running::start();
+		let deadline = process::deadline(config, Instant::now());
+
+		let mut command = config.command(placeholders)?;
+
+		let mut child = process::isolate(&mut command)
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.spawn()?;
+
+		let stdin = child.stdin.take().ok_or_else(|| PluginError::Protocol {
+			plugin: config.name.clone(),
+			message: String::from("stdin couldn't be attached"),
+		})?;
+		let stdout = child.stdout.take().ok_or_else(|| PluginError::Protocol {
+			plugin: config.name.clone(),
+			message: String::from("stdout couldn't be attached"),
+		})?;
+
+		if let Some(stderr) = child.stderr.take() {
+			process::pump(&config.name, Stream::Stderr, stderr);
+		}
+
+		let mut session = Session {
+			plugin: config.name.clone(),
+			config: config.clone(),
+			child: child,
+			stdin: Some(stdin),
+			lines: read_lines(stdout),
+			deadline: deadline,
+			version: 0,
+			capabilities: Vec::new(),
+			next_id: 1,
+			_running: running,
+		};
+
+		session.send(&Request::Handshake {
+			version: PROTOCOL_VERSION,
+			godwit: env!("CARGO_PKG_VERSION").to_string(),
+		})?;
+
+		match session.receive()? {
+			Response::Handshake {
+				version,
+				capabilities,
+				..
+			} if version == PROTOCOL_VERSION => {
+				session.version = version;
+				session.capabilities = capabilities;
+				Ok(session)
+			}
+			Response::Handshake { version, .. } => Err(PluginError::ProtocolMismatch {
+				plugin: session.plugin.clone(),
+				expected: PROTOCOL_VERSION,
+				found: version,
+			}),
+			_ => Err(PluginError::Protocol {
+				plugin: session.plugin.clone(),
+				message: String::from("expected a handshake"),
+			}),
+		}
+	}
+
+	/// Returns negotiated protocol version.
+	pub fn version(&self) -> u32 {
+		self.version
+	}
+
+	/// Returns methods advertised by the plugin.
+	pub fn capabilities(&self) -> &[String] {
+		&self.capabilities
+	}
+
+	/// Checks whether the plugin advertised a method.
+	pub fn supports(&self, method: &str) -> bool {
+		self.capabilities
+			.iter()
+			.any(|capability| capability == method)
+	}
+
+	/// Calls a plugin method and waits for its result. The call is recorded
+	/// in the audit log.
+	pub fn call(
+		&mut self,
+		method: &str,
+		context: Context,
+		args: Vec<String>,
+	) -> Result<Value, PluginError> {
+		let mut call_args = vec![method.to_string()];
+		call_args.extend(args.iter().cloned());
+
+		let entry = Entry::start(
+			&self.config,
+			&Placeholders {
+				glyph: context.glyph.clone(),
+				dir: context.directory.clone(),
+				args: call_args,
+			},
+		);
+
+		let result = self.request(method, context, args);
+
+		audit::record(&match &result {
+			Ok(value) => entry.finish(None, value.to_string().as_bytes(), &[], None),
+			Err(e) => entry.finish(None, &[], &[], Some(e)),
+		});
+
+		result
+	}
+
+	/// Sends a call and waits for its response.
+	fn request(
+		&mut self,
+		method: &str,
+		context: Context,
+		args: Vec<String>,
+	) -> Result<Value, PluginError> {
+		if !self.supports(method) {
+			return Err(PluginError::Unsupported {
+				plugin: self.plugin.clone(),
+				method: method.to_string(),
+			});
+		}
+
+		let id = self.next_id;
+		self.next_id += 1;
+
+		self.send(&Request::Call {
+			id: id,
+			method: method.to_string(),
+			context: Box::new(context),
+			args: args,
+		})?;
+
+		match self.receive()? {
+			Response::Result { id: r_id, value } if r_id == id => Ok(value),
+			Response::Error {
+				id: r_id,
+				code,
+				message,
+			} if r_id == id => Err(PluginError::Remote {
+				plugin: self.plugin.clone(),
+				code: code,
+				message: message,
+			}),
+			_ => Err(PluginError::Protocol {
+				plugin: self.plugin.clone(),
+				message: format!("expected a response to call {}", id),
+			}),
+		}
+	}
+
+	/// Ends the session and waits for the plugin to exit.
+	pub fn close(mut self) -> Result<(), PluginError> {
+		self.send(&Request::Shutdown)?;
+		self.stdin = None;
+
+		let status = process::wait(&self.config, &mut self.child, self.deadline)?;
+
+		if !status.success() {
+			return Err(PluginError::Protocol {
+				plugin: self.plugin.clone(),
+				message: format!("exited with {}", status),
+			});
+		}
+
+		Ok(())
+	}
+
+	/// Writes a message line.
+	fn send(&mut self, request: &Request) -> Result<(), PluginError> {
+		let line = serde_json::to_string(request)?;
+		debug!("{} < {}", self.plugin, line);
+
+		let plugin = &self.plugin;
+		let stdin = self.stdin.as_mut().ok_or_else(|| PluginError::Protocol {
+			plugin: plugin.clone(),
+			message: String::from("the session is closed"),
+		})?;
+
+		writeln!(stdin, "{}", line)?;
+		stdin.flush()?;
+		Ok(())
+	}
+
+	/// Reads a message line, unless interrupted or out of time.
+	fn receive(&mut self) -> Result<Response, PluginError> {
+		let line = loop {
+			match self.lines.recv_timeout(process::POLL_INTERVAL) {
+				Ok(line) => break line?,
+				Err(RecvTimeoutError::Timeout) => {
+					process::check(&self.config, &mut self.child, self.deadline)?
+				}
+				Err(RecvTimeoutError::Disconnected) => {
+					return Err(PluginError::Protocol {
+						plugin: self.plugin.clone(),
+						message: String::from("closed stdout before responding"),
+					});
+				}
+			}
+		};
+
+		debug!("{} > {}", self.plugin, line.trim_end());
+
+		serde_json::from_str(&line).map_err(|e| PluginError::Protocol {
+			plugin: self.plugin.clone(),
+			message: format!("malformed message: {}", e),
+		})
+	}
+}
+
+impl Drop for Session {
+	fn drop(&mut self) {
+		self.stdin = None;
+
+		if let Ok(None) = self.child.try_wait() {
+			debug!("Ending {} left running.", self.plugin);
+			process::kill(&mut self.child);
+		}
+	}
+}
+
+/// Reads lines of a plugin pipe on a separate thread.
+fn read_lines(stdout: ChildStdout) -> Receiver<io::Result<String>> {
+	let (sender, receiver) = mpsc::channel();
+
+	thread::spawn(move || {
+		for line in BufReader::new(stdout).lines() {
+			if sender.send(line).is_err() {
+				break;
+			}
+		}
+	});
+
+	receiver
+}
+
+/// Opens a session, makes a single call and closes the session.
+pub fn call_once(
+	config: &PluginConfig,
+	method: &str,
+	context: Context,
+	args: Vec<String>,
+) -> Result<Value, PluginError> {
+	let placeholders = Placeholders {
+		glyph: context.glyph.clone(),
+		dir: context.directory.clone(),
+		..Default::default()
+	};
+
+	let mut session = Session::start(config, &placeholders)?;
+	let value = session.call(method, context, args)?;
+	session.close()?;
+	Ok(value)
+}
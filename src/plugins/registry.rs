@@ -0,0 +1,108 @@
+//! Plugin Registry
+//!
+//! Collects the plugins fired on lifecycle events: built-ins first, then
+//! plugins registered in-process, then configured executables. A plugin
+//! shadows any earlier one sharing its name.
+use crate::errors::PluginError;
+use crate::plugins::builtin::{DaemonNotifier, Sanity};
+use crate::plugins::exec::ExecPlugin;
+use crate::plugins::{Event, Plugin, PluginConfig};
+use crate::settings;
+use log::debug;
+use std::sync::{Arc, RwLock};
+
+/// Plugins registered in-process by library users.
+static REGISTERED: RwLock<Vec<Arc<dyn Plugin>>> = RwLock::new(Vec::new());
+
+/// Ordered set of plugins.
+#[derive(Clone, Default)]
+pub struct Registry {
+	plugins: Vec<Arc<dyn Plugin>>,
+}
+
+impl Registry {
+	/// Returns an empty registry.
+	pub fn new() -> Self {
+		Registry {
+			plugins: Vec::new(),
+		}
+	}
+
+	/// Adds a plugin, replacing any plugin of the same name.
+	pub fn insert(&mut self, plugin: Arc<dyn Plugin>) -> &mut Self {
+		self.plugins
+			.retain(|p_plugin| p_plugin.name() != plugin.name());
+		self.plugins.push(plugin);
+		self
+	}
+
+	/// Looks up a plugin by name.
+	pub fn get(&self, name: &str) -> Option<Arc<dyn Plugin>> {
+		self.plugins
+			.iter()
+			.find(|plugin| plugin.name() == name)
+			.cloned()
+	}
+
+	/// Lists plugin names in firing order.
+	pub fn names(&self) -> Vec<String> {
+		self.plugins
+			.iter()
+			.map(|plugin| plugin.name().to_string())
+			.collect()
+	}
+
+	/// Fires an event on every plugin in order, stopping at the first
+	/// failure.
+	pub fn fire(&self, event: &Event) -> Result<(), PluginError> {
+		for plugin in &self.plugins {
+			debug!("Firing {} on {}.", event.hook(), plugin.name());
+			plugin.on_event(event)?;
+		}
+		Ok(())
+	}
+}
+
+/// Registers an in-process plugin, replacing any plugin of the same name.
+pub fn register<P>(plugin: P)
+where
+	P: Plugin + 'static,
+{
+	if let Ok(mut registered) = REGISTERED.write() {
+		registered.retain(|p_plugin| p_plugin.name() != plugin.name());
+		registered.push(Arc::new(plugin));
+	}
+}
+
+/// Drops an in-process plugin. Returns whether it was registered.
+pub fn unregister(name: &str) -> bool {
+	REGISTERED.write().map_or(false, |mut registered| {
+		let count = registered.len();
+		registered.retain(|plugin| plugin.name() != name);
+		registered.len() != count
+	})
+}
+
+/// Returns every plugin to fire on lifecycle events.
+pub fn registry() -> Result<Registry, PluginError> {
+	let mut registry = Registry::new();
+
+	registry.insert(Arc::new(Sanity));
+	registry.insert(Arc::new(DaemonNotifier));
+
+	if let Ok(registered) = REGISTERED.read() {
+		for plugin in registered.iter() {
+			registry.insert(plugin.clone());
+		}
+	}
+
+	for config in settings::get_settings()?
+		.get_plugins()
+		.into_iter()
+		.filter(PluginConfig::get_enabled)
+	{
+		registry.insert(Arc::new(ExecPlugin::new(config)));
+	}
+
+	Ok(registry)
+}
@@ -0,0 +1,101 @@
+//! Weaver
+//!
+//! Scaffolds new projects. The configured `Weaver` plugin receives the
+//! glyph, target location and template choice, as a `scaffold` call for
+//! protocol plugins or as arguments and `GODWIT_*` environment variables
+//! otherwise. Without an enabled Weaver, the native scaffolder takes over.
+use crate::errors::PluginError;
+use crate::glyph::Glyph;
+use crate::plugins::builtin;
+use crate::plugins::command::Placeholders;
+use crate::plugins::process;
+use crate::plugins::protocol::{self, Context};
+use crate::plugins::{get_plugin, PluginConfig};
+use crate::settings;
+use log::debug;
+use std::path::Path;
+
+/// Name of the scaffolding plugin.
+pub const WEAVER: &str = "Weaver";
+
+/// Protocol method called on the scaffolding plugin.
+pub const SCAFFOLD_METHOD: &str = "scaffold";
+
+/// Scaffolds a new project through Weaver, or the built-in scaffolder if
+/// Weaver isn't configured.
+pub fn weave(glyph: &Glyph, location: &Path, template: Option<&str>) -> Result<(), PluginError> {
+	match get_plugin(WEAVER) {
+		Ok(plugin) if plugin.get_protocol() => weave_protocol(&plugin, glyph, location, template),
+		Ok(plugin) => weave_exec(&plugin, glyph, location, template),
+		Err(PluginError::PluginNotFound { .. }) | Err(PluginError::PluginDisabled { .. }) => {
+			debug!("{} isn't enabled, using native scaffolder.", WEAVER);
+			builtin::scaffold(glyph, location, template)
+		}
+		Err(e) => Err(e),
+	}
+}
+
+/// Calls `scaffold` on a protocol Weaver, with the template as argument.
+fn weave_protocol(
+	plugin: &PluginConfig,
+	glyph: &Glyph,
+	location: &Path,
+	template: Option<&str>,
+) -> Result<(), PluginError> {
+	let context = Context {
+		glyph: Some(glyph.clone()),
+		directory: Some(location.to_path_buf()),
+		settings: serde_json::to_value(settings::get_settings()?)?,
+		..Default::default()
+	};
+
+	let args = template.map(String::from).into_iter().collect();
+
+	protocol::call_once(plugin, SCAFFOLD_METHOD, context, args)?;
+	Ok(())
+}
+
+/// Runs an executable Weaver with `<glyph> <location> [template]` as
+/// arguments.
+fn weave_exec(
+	plugin: &PluginConfig,
+	glyph: &Glyph,
+	location: &Path,
+	template: Option<&str>,
+) -> Result<(), PluginError> {
+	let glyph_str = glyph.to_string();
+
+	let mut args = vec![glyph_str.clone(), location.to_string_lossy().into_owned()];
+	args.extend(template.map(String::from));
+
+	let placeholders = Placeholders {
+		glyph: Some(glyph.clone()),
+		dir: Some(location.to_path_buf()),
+		args: args,
+	};
+
+	let mut command = plugin.command(&placeholders)?;
+	command
+		.env("GODWIT_HOOK", SCAFFOLD_METHOD)
+		.env("GODWIT_GLYPH", &glyph_str)
+		.env("GODWIT_DIR", location)
+		.env("GODWIT_TEMPLATE", template.unwrap_or_default());
+
+	let output = process::run(plugin, command, &placeholders)?;
+
+	if !output.status.success() {
+		let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+
+		return Err(PluginError::HookFailed {
+			plugin: plugin.get_name(),
+			hook: SCAFFOLD_METHOD.to_string(),
+			message: if stderr.is_empty() {
+				output.status.to_string()
+			} else {
+				stderr
+			},
+		});
+	}
+
+	Ok(())
+}
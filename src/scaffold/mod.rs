@@ -0,0 +1,245 @@
+//! Scaffolder
+//!
+//! Native project scaffolding from template directories. A template is a
+//! directory looked up as a path, then under `scaffolds/` in the working
+//! directory. Its files are copied into the new project location, with
+//! placeholders substituted in file names and text contents. File modes and
+//! symbolic links are kept as they are.
+//!
+//! Available placeholders are `{{glyph}}`, `{{glyph.tag}}`, `{{glyph.id}}`,
+//! `{{date}}`, `{{year}}` and one per prompt declared in an optional
+//! `scaffold.gwcore` manifest at the template root, which isn't copied.
+//!
+//! ```json
+//! {
+//!   "prompts": [
+//!     { "name": "description", "message": "Project description", "default": "" }
+//!   ]
+//! }
+//! ```
+use crate::errors::ScaffoldError;
+use crate::format;
+use crate::glyph::Glyph;
+use crate::iohandler::scanner;
+use crate::settings;
+use chrono::Local;
+use getter_derive::Getter;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Manifest file name at the root of a scaffold template.
+pub const MANIFEST_FILE: &str = "scaffold.gwcore";
+
+/// Value asked for on scaffolding.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, Getter)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct Prompt {
+	name: String,
+	#[serde(default)]
+	message: Option<String>,
+	#[serde(default)]
+	default: Option<String>,
+}
+
+/// Scaffold template manifest.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, Getter)]
+#[serde(rename_all = "snake_case", default, deny_unknown_fields)]
+pub struct Manifest {
+	prompts: Vec<Prompt>,
+}
+
+/// Returns user scaffold templates directory.
+pub fn get_scaffolds_dir() -> PathBuf {
+	settings::get_working_dir().join("scaffolds")
+}
+
+/// Finds a scaffold template directory by path or user template name.
+pub fn find_template(template: &str) -> Result<PathBuf, ScaffoldError> {
+	let path = Path::new(template);
+
+	if path.is_dir() {
+		return Ok(path.to_path_buf());
+	}
+
+	let user_template = get_scaffolds_dir().join(template);
+
+	if user_template.is_dir() {
+		return Ok(user_template);
+	}
+
+	Err(ScaffoldError::TemplateNotFound {
+		template: template.to_string(),
+	})
+}
+
+/// Reads the manifest of a template directory, if any.
+fn read_manifest(template: &str, dir: &Path) -> Result<Manifest, ScaffoldError> {
+	match format::find_variant(&dir.join(MANIFEST_FILE)) {
+		Some(path) => format::read(&path).map_err(|e| ScaffoldError::InvalidManifest {
+			template: template.to_string(),
+			message: e.to_string(),
+		}),
+		None => Ok(Manifest::default()),
+	}
+}
+
+/// Collects placeholder values, prompting for those not given.
+fn collect_vars(
+	glyph: &Glyph,
+	manifest: &Manifest,
+	mut values: BTreeMap<String, String>,
+) -> Result<Vec<(String, String)>, ScaffoldError> {
+	let now = Local::now();
+
+	let mut vars = vec![
+		(String::from("{{glyph}}"), glyph.to_string()),
+		(String::from("{{glyph.tag}}"), glyph.tag.clone()),
+		(String::from("{{glyph.id}}"), glyph.id.clone()),
+		(String::from("{{date}}"), now.format("%Y-%m-%d").to_string()),
+		(String::from("{{year}}"), now.format("%Y").to_string()),
+	];
+
+	for prompt in manifest.get_prompts() {
+		let value = match values.remove(&prompt.name) {
+			Some(value) => value,
+			None => scanner::ask(
+				prompt.message.as_deref().unwrap_or(&prompt.name),
+				prompt.default.as_deref(),
+			)?,
+		};
+
+		vars.push((format!("{{{{{}}}}}", prompt.name), value));
+	}
+
+	for (name, value) in values {
+		vars.push((format!("{{{{{}}}}}", name), value));
+	}
+
+	Ok(vars)
+}
+
+/// Replaces placeholders in a string.
+fn substitute(string: &str, vars: &[(String, String)]) -> String {
+	vars.iter()
+		.fold(string.to_string(), |string, (var, replacement)| {
+			string.replace(var, replacement)
+		})
+}
+
+/// Substitutes placeholders in a file name, which must remain a single
+/// path component.
+fn substitute_name(name: &str, vars: &[(String, String)]) -> Result<String, ScaffoldError> {
+	let substituted = substitute(name, vars);
+
+	if substituted.is_empty()
+		|| substituted == "."
+		|| substituted == ".."
+		|| substituted.contains(['/', '\\'])
+	{
+		return Err(ScaffoldError::InvalidName {
+			name: name.to_string(),
+			substituted: substituted,
+		});
+	}
+
+	Ok(substituted)
+}
+
+/// Checks whether a path is a template manifest.
+fn is_manifest(path: &Path) -> bool {
+	format::strip_format(path).file_name() == Some(MANIFEST_FILE.as_ref())
+}
+
+/// Copies a template directory recursively, substituting placeholders. The
+/// manifest is skipped at the template root.
+fn copy_dir(
+	source: &Path,
+	target: &Path,
+	vars: &[(String, String)],
+	root: bool,
+) -> Result<(), ScaffoldError> {
+	fs::create_dir_all(target)?;
+
+	for entry in fs::read_dir(source)? {
+		let entry = entry?;
+
+		if root && is_manifest(&entry.path()) {
+			continue;
+		}
+
+		let file_name = substitute_name(&entry.file_name().to_string_lossy(), vars)?;
+		let target_path = target.join(file_name);
+		let metadata = fs::symlink_metadata(entry.path())?;
+
+		if metadata.file_type().is_symlink() {
+			debug!("Linking {}", target_path.display());
+			copy_link(&entry.path(), &target_path)?;
+			continue;
+		}
+
+		if metadata.is_dir() {
+			copy_dir(&entry.path(), &target_path, vars, false)?;
+			fs::set_permissions(&target_path, metadata.permissions())?;
+			continue;
+		}
+
+		debug!("Scaffolding {}", target_path.display());
+
+		let contents = fs::read(entry.path())?;
+
+		match String::from_utf8(contents) {
+			Ok(text) => fs::write(&target_path, substitute(&text, vars))?,
+			Err(e) => fs::write(&target_path, e.into_bytes())?,
+		}
+
+		fs::set_permissions(&target_path, metadata.permissions())?;
+	}
+
+	Ok(())
+}
+
+/// Recreates a symbolic link, pointing where the original does.
+#[cfg(unix)]
+fn copy_link(source: &Path, target: &Path) -> Result<(), ScaffoldError> {
+	std::os::unix::fs::symlink(fs::read_link(source)?, target)?;
+	Ok(())
+}
+
+/// Copies whatever a symbolic link points to, as links need privileges.
+#[cfg(not(unix))]
+fn copy_link(source: &Path, target: &Path) -> Result<(), ScaffoldError> {
+	fs::copy(source, target)?;
+	Ok(())
+}
+
+/// Scaffolds a project from a template into an empty or missing location.
+/// Prompt values not given are asked for on stdio.
+pub fn scaffold(
+	glyph: &Glyph,
+	location: &Path,
+	template: &str,
+	values: BTreeMap<String, String>,
+) -> Result<(), ScaffoldError> {
+	let template_dir = find_template(template)?;
+
+	if location.is_file() || (location.is_dir() && fs::read_dir(location)?.next().is_some()) {
+		return Err(ScaffoldError::TargetNotEmpty {
+			path: location.to_string_lossy().into_owned(),
+		});
+	}
+
+	let manifest = read_manifest(template, &template_dir)?;
+	let vars = collect_vars(glyph, &manifest, values)?;
+
+	info!(
+		"Scaffolding {} from {} at {}",
+		glyph,
+		template_dir.display(),
+		location.display()
+	);
+
+	copy_dir(&template_dir, location, &vars, true)
+}
@@ -0,0 +1,430 @@
+//! Secrets Vault
+//!
+//! Per-project secrets store kept under the Godwit working directory. Values
+//! are sealed with a key derived from a passphrase and are only ever decrypted
+//! for the glyph they belong to. The vault file is only readable by its owner
+//! and is replaced whole on every save. Values known to be secret can be
+//! redacted from logs.
+use crate::errors::{SecretError, SettingsError};
+use crate::glyph::Glyph;
+use crate::settings;
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// Environment variable consulted for the vault passphrase before prompting.
+pub const PASSPHRASE_VAR: &str = "GODWIT_PASSPHRASE";
+
+/// Key derivation rounds for new vaults.
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Known plaintext sealed on creation to verify passphrases.
+const CHECK_PLAINTEXT: &str = "godwit";
+
+/// Replacement of redacted secret values.
+pub const REDACTED: &str = "[redacted]";
+
+/// Length under which values aren't redacted, as they'd match anywhere.
+const MIN_REDACTED_LEN: usize = 4;
+
+/// Secret values stored or decrypted in this run.
+static KNOWN: RwLock<BTreeSet<String>> = RwLock::new(BTreeSet::new());
+
+/// Encrypted value with its nonce.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+struct Sealed {
+	nonce: String,
+	data: String,
+}
+
+/// On-disk vault layout.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+struct VaultFile {
+	salt: String,
+	iterations: u32,
+	check: Sealed,
+	secrets: BTreeMap<String, BTreeMap<String, Sealed>>,
+}
+
+/// Unlocked secrets vault.
+pub struct Vault {
+	path: PathBuf,
+	key: LessSafeKey,
+	contents: VaultFile,
+}
+
+impl Vault {
+	/// Unlocks the vault with a passphrase from the environment or prompted
+	/// for. A new vault has its passphrase prompted for twice.
+	pub fn unlock() -> Result<Self, SecretError> {
+		if get_vault_path()?.exists() {
+			Vault::open(&get_passphrase()?)
+		} else {
+			Vault::open(&get_new_passphrase()?)
+		}
+	}
+
+	/// Unlocks the vault, creating a new one if none exists.
+	pub fn open(passphrase: &str) -> Result<Self, SecretError> {
+		let path = get_vault_path()?;
+
+		if !path.exists() {
+			return Vault::create(path, passphrase);
+		}
+
+		let contents: VaultFile = File::open(&path).and_then(|vault_file| {
+			let contents = serde_json::from_reader(vault_file)?;
+			Ok(contents)
+		})?;
+
+		let key = derive_key(
+			passphrase,
+			&base64::decode(&contents.salt)?,
+			contents.iterations,
+		)?;
+
+		let check =
+			unseal(&key, "", &contents.check).map_err(|_| SecretError::InvalidPassphrase)?;
+
+		if check != CHECK_PLAINTEXT {
+			return Err(SecretError::InvalidPassphrase);
+		}
+
+		Ok(Vault {
+			path: path,
+			key: key,
+			contents: contents,
+		})
+	}
+
+	/// Creates an empty vault sealed with given passphrase.
+	fn create(path: PathBuf, passphrase: &str) -> Result<Self, SecretError> {
+		let mut salt = [0u8; 16];
+		SystemRandom::new()
+			.fill(&mut salt)
+			.map_err(|_| SecretError::Crypto)?;
+
+		let key = derive_key(passphrase, &salt, PBKDF2_ITERATIONS)?;
+
+		let contents = VaultFile {
+			salt: base64::encode(salt),
+			iterations: PBKDF2_ITERATIONS,
+			check: seal(&key, "", CHECK_PLAINTEXT)?,
+			secrets: Default::default(),
+		};
+
+		Ok(Vault {
+			path: path,
+			key: key,
+			contents: contents,
+		})
+	}
+
+	/// Stores a secret for a glyph.
+	pub fn set(&mut self, glyph: &Glyph, name: &str, value: &str) -> Result<(), SecretError> {
+		let sealed = seal(&self.key, &secret_aad(glyph, name), value)?;
+		remember(value);
+
+		self.contents
+			.secrets
+			.entry(glyph.to_string())
+			.or_default()
+			.insert(name.to_string(), sealed);
+
+		Ok(())
+	}
+
+	/// Decrypts a secret of a glyph.
+	pub fn get(&self, glyph: &Glyph, name: &str) -> Result<String, SecretError> {
+		self.contents
+			.secrets
+			.get(&glyph.to_string())
+			.and_then(|secrets| secrets.get(name))
+			.map_or_else(
+				|| {
+					Err(SecretError::SecretNotFound {
+						secret: name.to_string(),
+						glyph: glyph.to_string(),
+					})
+				},
+				|sealed| {
+					let value = unseal(&self.key, &secret_aad(glyph, name), sealed)?;
+					remember(&value);
+					Ok(value)
+				},
+			)
+	}
+
+	/// Drops a secret of a glyph.
+	pub fn remove(&mut self, glyph: &Glyph, name: &str) -> Result<(), SecretError> {
+		let secrets = self.contents.secrets.get_mut(&glyph.to_string());
+
+		if secrets.and_then(|secrets| secrets.remove(name)).is_none() {
+			return Err(SecretError::SecretNotFound {
+				secret: name.to_string(),
+				glyph: glyph.to_string(),
+			});
+		}
+
+		self.contents
+			.secrets
+			.retain(|_, secrets| !secrets.is_empty());
+
+		Ok(())
+	}
+
+	/// Lists secret names of a glyph.
+	pub fn list(&self, glyph: &Glyph) -> Vec<String> {
+		self.contents
+			.secrets
+			.get(&glyph.to_string())
+			.map(|secrets| secrets.keys().cloned().collect())
+			.unwrap_or_default()
+	}
+
+	/// Decrypts every secret of a glyph.
+	pub fn export(&self, glyph: &Glyph) -> Result<BTreeMap<String, String>, SecretError> {
+		self.list(glyph)
+			.into_iter()
+			.map(|name| {
+				let value = self.get(glyph, &name)?;
+				Ok((name, value))
+			})
+			.collect()
+	}
+
+	/// Commits changes to the vault file, through an owner-only temporary
+	/// file renamed over it.
+	pub fn save(&self) -> Result<(), SecretError> {
+		let mut temp_name = self.path.file_name().unwrap_or_default().to_os_string();
+		temp_name.push(".tmp");
+		let temp_path = self.path.with_file_name(temp_name);
+
+		let mut options = OpenOptions::new();
+		options.write(true).create(true).truncate(true);
+
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::OpenOptionsExt;
+			options.mode(0o600);
+		}
+
+		let mut vault_file = options.open(&temp_path)?;
+		vault_file
+			.write_all(&serde_json::to_vec_pretty(&self.contents).map_err(io::Error::from)?)?;
+		vault_file.sync_all()?;
+
+		fs::rename(&temp_path, &self.path)?;
+		Ok(())
+	}
+}
+
+/// Returns vault file location in the working directory.
+pub fn get_vault_path() -> Result<PathBuf, SecretError> {
+	let settings = settings::get_settings()?;
+
+	if settings.get_headless() {
+		return Err(SettingsError::DisallowedHeadless.into());
+	}
+
+	Ok(settings
+		.get_working_dir()
+		.unwrap_or_default()
+		.join("secrets.gwvault"))
+}
+
+/// Reads the vault passphrase from environment or prompts for it.
+pub fn get_passphrase() -> Result<String, SecretError> {
+	match env::var(PASSPHRASE_VAR) {
+		Ok(passphrase) => Ok(passphrase),
+		Err(_) => Ok(rpassword::read_password_from_tty(Some(
+			"Godwit vault passphrase: ",
+		))?),
+	}
+}
+
+/// Reads a new vault passphrase from environment or prompts for it twice.
+pub fn get_new_passphrase() -> Result<String, SecretError> {
+	if let Ok(passphrase) = env::var(PASSPHRASE_VAR) {
+		return Ok(passphrase);
+	}
+
+	let passphrase = rpassword::read_password_from_tty(Some("New Godwit vault passphrase: "))?;
+	let confirmation = rpassword::read_password_from_tty(Some("Repeat the passphrase: "))?;
+
+	if passphrase != confirmation {
+		return Err(SecretError::PassphraseMismatch);
+	}
+
+	Ok(passphrase)
+}
+
+/// Reads a secret value from a hidden prompt, or from stdin when piped. A
+/// single trailing newline of piped input is dropped.
+pub fn read_value(name: &str) -> Result<String, SecretError> {
+	if unsafe { libc::isatty(libc::STDIN_FILENO) } == 1 {
+		return Ok(rpassword::read_password_from_tty(Some(&format!(
+			"Value for {}: ",
+			name
+		)))?);
+	}
+
+	let mut value = String::new();
+	io::stdin().read_to_string(&mut value)?;
+
+	if value.ends_with('\n') {
+		value.pop();
+		if value.ends_with('\r') {
+			value.pop();
+		}
+	}
+
+	Ok(value)
+}
+
+/// Unlocks the vault for injecting secrets of some glyphs. Skips unlocking
+/// when there's no vault or it holds nothing for them.
+pub fn unlock_for(glyphs: &[Glyph]) -> Result<Option<Vault>, SecretError> {
+	// Headless usage keeps no vault.
+	if settings::get_settings()?.get_headless() {
+		return Ok(None);
+	}
+
+	let path = get_vault_path()?;
+
+	if !path.exists() {
+		return Ok(None);
+	}
+
+	let contents: VaultFile = File::open(&path).and_then(|vault_file| {
+		let contents = serde_json::from_reader(vault_file)?;
+		Ok(contents)
+	})?;
+
+	if !glyphs
+		.iter()
+		.any(|glyph| contents.secrets.contains_key(&glyph.to_string()))
+	{
+		return Ok(None);
+	}
+
+	Ok(Some(Vault::open(&get_passphrase()?)?))
+}
+
+/// Decrypts secrets of a glyph for injection. Skips unlocking when there's no
+/// vault or it holds nothing for the glyph.
+pub fn export_secrets(glyph: &Glyph) -> Result<BTreeMap<String, String>, SecretError> {
+	match unlock_for(std::slice::from_ref(glyph))? {
+		Some(vault) => vault.export(glyph),
+		None => Ok(Default::default()),
+	}
+}
+
+/// Keeps a secret value known for redaction.
+fn remember(value: &str) {
+	if let Ok(mut known) = KNOWN.write() {
+		known.insert(value.to_string());
+	}
+}
+
+/// Returns values known to be secret: those stored or decrypted in this run
+/// and those of environment variables named after stored secrets, as
+/// exported to shells. Longest values come first.
+pub fn known_values() -> Vec<String> {
+	let mut values = KNOWN.read().map(|known| known.clone()).unwrap_or_default();
+
+	let contents = get_vault_path().ok().and_then(|path| {
+		let vault_file = File::open(path).ok()?;
+		serde_json::from_reader::<_, VaultFile>(vault_file).ok()
+	});
+
+	for names in contents
+		.iter()
+		.flat_map(|contents| contents.secrets.values())
+	{
+		values.extend(names.keys().filter_map(|name| env::var(name).ok()));
+	}
+
+	let mut values = values
+		.into_iter()
+		.filter(|value| value.len() >= MIN_REDACTED_LEN)
+		.collect::<Vec<_>>();
+
+	values.sort_by_key(|value| std::cmp::Reverse(value.len()));
+	values
+}
+
+/// Replaces known secret values in some text.
+pub fn redact(text: &str, values: &[String]) -> String {
+	values.iter().fold(text.to_string(), |text, value| {
+		text.replace(value.as_str(), REDACTED)
+	})
+}
+
+/// Binds sealed values to their owner so they cannot be swapped around.
+fn secret_aad(glyph: &Glyph, name: &str) -> String {
+	format!("{}:{}", glyph, name)
+}
+
+/// Derives vault key from passphrase.
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> Result<LessSafeKey, SecretError> {
+	let iterations = NonZeroU32::new(iterations).ok_or(SecretError::Crypto)?;
+
+	let mut key_bytes = [0u8; 32];
+	pbkdf2::derive(
+		pbkdf2::PBKDF2_HMAC_SHA256,
+		iterations,
+		salt,
+		passphrase.as_bytes(),
+		&mut key_bytes,
+	);
+
+	let unbound_key =
+		UnboundKey::new(&aead::CHACHA20_POLY1305, &key_bytes).map_err(|_| SecretError::Crypto)?;
+
+	Ok(LessSafeKey::new(unbound_key))
+}
+
+/// Encrypts a value under a fresh nonce.
+fn seal(key: &LessSafeKey, aad: &str, value: &str) -> Result<Sealed, SecretError> {
+	let mut nonce = [0u8; aead::NONCE_LEN];
+	SystemRandom::new()
+		.fill(&mut nonce)
+		.map_err(|_| SecretError::Crypto)?;
+
+	let mut data = value.as_bytes().to_vec();
+	key.seal_in_place_append_tag(
+		Nonce::assume_unique_for_key(nonce),
+		Aad::from(aad.as_bytes()),
+		&mut data,
+	)
+	.map_err(|_| SecretError::Crypto)?;
+
+	Ok(Sealed {
+		nonce: base64::encode(nonce),
+		data: base64::encode(&data),
+	})
+}
+
+/// Decrypts a sealed value.
+fn unseal(key: &LessSafeKey, aad: &str, sealed: &Sealed) -> Result<String, SecretError> {
+	let nonce = Nonce::try_assume_unique_for_key(&base64::decode(&sealed.nonce)?)
+		.map_err(|_| SecretError::Crypto)?;
+
+	let mut data = base64::decode(&sealed.data)?;
+	let value = key
+		.open_in_place(nonce, Aad::from(aad.as_bytes()), &mut data)
+		.map_err(|_| SecretError::Crypto)?;
+
+	Ok(String::from_utf8(value.to_vec())?)
+}
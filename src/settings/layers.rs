@@ -0,0 +1,335 @@
+//! Layered Settings
+//!
+//! Resolves effective settings by stacking built-in defaults, system, user
+//! and project settings and `GODWIT_*` environment overrides, in that order.
+//! Objects merge key by key while any other value replaces what lies below.
+//! Project settings only set hooks, plugins, env and directories once trusted.
+use crate::errors::SettingsError;
+use crate::format;
+use crate::settings::trust::{self, GATED_FIELDS};
+use crate::settings::Settings;
+use log::{info, warn};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// System-wide settings source. Like other settings files, it may carry a
+/// format extension.
+pub const SYSTEM_SETTINGS: &str = "/etc/godwit/settings.gwcore";
+
+/// Project settings source, looked up from the current directory upwards.
+pub const PROJECT_SETTINGS: &str = ".gw/settings.gwcore";
+
+/// Prefix of settings overrides in the environment.
+pub const ENV_PREFIX: &str = "GODWIT_";
+
+/// Key separator in environment overrides (`GODWIT_ENV__AWS_PROFILE`).
+const ENV_SEPARATOR: &str = "__";
+
+/// Prefixed environment variables that aren't settings overrides, as Godwit
+/// reads them itself or sets them for hooks and plugins.
+const ENV_RESERVED: &[&str] = &[
+	"GODWIT_HOME",
+	"GODWIT_PASSPHRASE",
+	"GODWIT_HOOK",
+	"GODWIT_GLYPH",
+	"GODWIT_DIR",
+	"GODWIT_TEMPLATE",
+	"GODWIT_OLD_DIR",
+	"GODWIT_NEW_DIR",
+	"GODWIT_ACTIVE",
+	"GODWIT_WORKING_DIR",
+	"GODWIT_PREV_GLYPH",
+	"GODWIT_PREV_DIR",
+];
+
+/// Warnings already given, keeping repeated resolutions quiet.
+static WARNED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Source of a settings value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Origin {
+	Default,
+	System(PathBuf),
+	User(PathBuf),
+	Project(PathBuf),
+	Env(String),
+}
+
+impl fmt::Display for Origin {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Origin::Default => write!(f, "default"),
+			Origin::System(path) => write!(f, "system ({})", path.display()),
+			Origin::User(path) => write!(f, "user ({})", path.display()),
+			Origin::Project(path) => write!(f, "project ({})", path.display()),
+			Origin::Env(var) => write!(f, "env ({})", var),
+		}
+	}
+}
+
+/// Single settings layer along with its source.
+#[derive(Clone, Debug)]
+pub struct Layer {
+	pub origin: Origin,
+	pub value: Value,
+}
+
+/// Effective settings with the origin of every value.
+#[derive(Clone, Debug)]
+pub struct Resolved {
+	pub value: Value,
+	pub origins: BTreeMap<String, Origin>,
+}
+
+impl Resolved {
+	/// Deserializes effective settings.
+	pub fn settings(&self) -> Result<Settings, SettingsError> {
+		serde_json::from_value(self.value.clone()).map_err(|e| {
+			SettingsError::InvalidEffectiveSettings {
+				message: e.to_string(),
+			}
+		})
+	}
+
+	/// Lists every effective value by dotted key along with its origin.
+	pub fn entries(&self) -> Vec<(String, Value, Origin)> {
+		self.origins
+			.iter()
+			.map(|(key, origin)| {
+				let value = lookup(&self.value, key).cloned().unwrap_or(Value::Null);
+				(key.clone(), value, origin.clone())
+			})
+			.collect()
+	}
+}
+
+/// Looks up a value by dotted key.
+pub fn lookup<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+	key.split('.')
+		.try_fold(value, |value, segment| value.get(segment))
+}
+
+/// Joins a dotted key with a segment.
+fn join_key(path: &str, key: &str) -> String {
+	if path.is_empty() {
+		key.to_string()
+	} else {
+		format!("{}.{}", path, key)
+	}
+}
+
+/// Checks whether a dotted key sits at or under a path.
+fn is_under(key: &str, path: &str) -> bool {
+	path.is_empty() || key == path || key.starts_with(&format!("{}.", path))
+}
+
+/// Records the origin of every leaf of a value.
+fn record(value: &Value, path: &str, origin: &Origin, origins: &mut BTreeMap<String, Origin>) {
+	match value {
+		Value::Object(map) if !map.is_empty() => {
+			for (key, value) in map {
+				record(value, &join_key(path, key), origin, origins);
+			}
+		}
+		_ => {
+			origins.insert(path.to_string(), origin.clone());
+		}
+	}
+}
+
+/// Merges an overlay into a base value, tracking origins.
+fn merge(
+	base: &mut Value,
+	overlay: Value,
+	path: &str,
+	origin: &Origin,
+	origins: &mut BTreeMap<String, Origin>,
+) {
+	match overlay {
+		Value::Object(overlay_map) if base.is_object() => {
+			if !overlay_map.is_empty() {
+				// An empty object stops being a leaf once it gains keys.
+				origins.remove(path);
+			}
+
+			if let Some(base_map) = base.as_object_mut() {
+				for (key, value) in overlay_map {
+					let key_path = join_key(path, &key);
+					merge(
+						base_map.entry(key).or_insert(Value::Null),
+						value,
+						&key_path,
+						origin,
+						origins,
+					);
+				}
+			}
+		}
+		overlay => {
+			origins.retain(|key, _| !is_under(key, path));
+			record(&overlay, path, origin, origins);
+			*base = overlay;
+		}
+	}
+}
+
+/// Reads a settings file as a layer.
+fn read_layer(path: &Path, origin: Origin) -> Result<Layer, SettingsError> {
+	let value = super::read_value(path)?;
+
+	Ok(Layer {
+		origin: origin,
+		value: value,
+	})
+}
+
+/// Finds the nearest project settings from the current directory upwards.
+pub fn find_project_settings() -> Option<PathBuf> {
+	env::current_dir().ok().and_then(|current_dir| {
+		current_dir
+			.ancestors()
+			.find_map(|dir| format::find_variant(&dir.join(PROJECT_SETTINGS)))
+	})
+}
+
+/// Builds a layer from one `GODWIT_*` environment override. The first key
+/// segment is lowercased to match settings fields, the rest is kept as is.
+fn env_layer(var: &str, raw_value: String) -> Option<Layer> {
+	if !var.starts_with(ENV_PREFIX) || ENV_RESERVED.contains(&var) {
+		return None;
+	}
+
+	let mut segments = var[ENV_PREFIX.len()..].split(ENV_SEPARATOR);
+	let field = segments
+		.next()
+		.filter(|field| !field.is_empty())?
+		.to_lowercase();
+
+	let leaf = serde_json::from_str(&raw_value).unwrap_or(Value::String(raw_value));
+
+	let value = segments
+		.collect::<Vec<_>>()
+		.into_iter()
+		.rev()
+		.chain(std::iter::once(field.as_str()))
+		.fold(leaf, |value, segment| {
+			let mut map = serde_json::Map::new();
+			map.insert(segment.to_string(), value);
+			Value::Object(map)
+		});
+
+	Some(Layer {
+		origin: Origin::Env(var.to_string()),
+		value: value,
+	})
+}
+
+/// Warns once per run.
+fn warn_once(message: String) {
+	if let Ok(mut warned) = WARNED.lock() {
+		if warned.insert(message.clone()) {
+			warn!("{}", message);
+		}
+	}
+}
+
+/// Drops gated fields of an untrusted layer.
+fn distrust(layer: &mut Layer) {
+	if let Some(map) = layer.value.as_object_mut() {
+		for field in GATED_FIELDS {
+			if map.remove(*field).is_some() {
+				warn_once(format!(
+					"Ignoring {} of untrusted {}. Trust it with `godwit config trust`.",
+					field, layer.origin
+				));
+			}
+		}
+	}
+}
+
+/// Warns of keys in a layer that aren't settings fields.
+fn check_fields(layer: &Layer) {
+	let defaults = serde_json::to_value(Settings::default()).unwrap_or(Value::Null);
+
+	if let Some(map) = layer.value.as_object() {
+		for key in map
+			.keys()
+			.filter(|key| defaults.get(key.as_str()).is_none())
+		{
+			warn_once(format!(
+				"Ignoring unknown settings key {} of {}.",
+				key, layer.origin
+			));
+		}
+	}
+}
+
+/// Collects every layer above the built-in defaults.
+pub fn collect_layers(user_path: &Path) -> Result<Vec<Layer>, SettingsError> {
+	let mut layers = Vec::new();
+
+	if let Some(system_path) = format::find_variant(Path::new(SYSTEM_SETTINGS)) {
+		info!("System settings found at {}", system_path.display());
+		layers.push(read_layer(
+			&system_path,
+			Origin::System(system_path.clone()),
+		)?);
+	}
+
+	let mut user_layer = read_layer(user_path, Origin::User(user_path.to_path_buf()))?;
+
+	// A state-graph embedded in headless settings isn't part of them.
+	if let Some(user_map) = user_layer.value.as_object_mut() {
+		user_map.remove(super::STATE_GRAPH_KEY);
+	}
+
+	layers.push(user_layer);
+
+	if let Some(project_path) = find_project_settings() {
+		info!("Project settings found at {}", project_path.display());
+		let mut project_layer = read_layer(&project_path, Origin::Project(project_path.clone()))?;
+
+		if !trust::is_trusted(&project_path) {
+			distrust(&mut project_layer);
+		}
+
+		layers.push(project_layer);
+	}
+
+	let mut vars = env::vars_os()
+		.filter_map(|(var, raw_value)| {
+			Some((var.into_string().ok()?, raw_value.into_string().ok()?))
+		})
+		.collect::<Vec<_>>();
+	vars.sort();
+
+	layers.extend(
+		vars.into_iter()
+			.filter_map(|(var, raw_value)| env_layer(&var, raw_value)),
+	);
+
+	Ok(layers)
+}
+
+/// Resolves effective settings on top of given user settings source.
+/// Unknown keys are warned of.
+pub fn resolve(user_path: &Path) -> Result<Resolved, SettingsError> {
+	let mut value = serde_json::to_value(Settings::default()).unwrap_or(Value::Null);
+	let mut origins = BTreeMap::new();
+
+	record(&value, "", &Origin::Default, &mut origins);
+
+	for layer in collect_layers(user_path)? {
+		check_fields(&layer);
+		merge(&mut value, layer.value, "", &layer.origin, &mut origins);
+	}
+
+	Ok(Resolved {
+		value: value,
+		origins: origins,
+	})
+}
@@ -1,24 +1,62 @@
 //! Godwit Settings Management
 //!
 //! A utility abstraction over persistent settings and access methods.
-use crate::errors::SettingsError;
-use crate::plugins::Plugin;
+pub mod layers;
+pub mod trust;
+
+use crate::errors::{FormatError, SettingsError};
+use crate::format::{self, Format};
+use crate::history::Retention;
+use crate::hooks::Hooks;
+use crate::plugins::PluginConfig;
 use getter_derive::Getter;
 use glob::glob;
 use log::info;
 use serde::{Deserialize, Serialize};
-use std::fs::{self, File};
-use std::path::PathBuf;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Settings file name inside a working directory, before any format
+/// extension.
+pub const SETTINGS_FILE: &str = "settings.gwcore";
+
+/// State-graph file name inside the states directory, before any format
+/// extension.
+pub const STATE_GRAPH_FILE: &str = "active.gwsg";
+
+/// Key of the state-graph embedded in headless settings.
+pub const STATE_GRAPH_KEY: &str = "state_graph";
+
+/// Environment variable overriding the Godwit working directory.
+pub const HOME_VAR: &str = "GODWIT_HOME";
+
+/// Working directory or settings file passed on the command line. Takes
+/// precedence over every other location source.
+static CONFIG_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
 
 /// Define godwit settings.
 #[derive(Clone, Debug, Deserialize, Serialize, Getter)]
-#[serde(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case", default)]
 pub struct Settings {
 	working_dir: Option<PathBuf>,
 	states_dir: Option<PathBuf>,
 	headless: bool,
 	switch_on_add: bool,
-	plugins: Vec<Plugin>,
+	plugins: Vec<PluginConfig>,
+	#[serde(default)]
+	env: BTreeMap<String, String>,
+	#[serde(default)]
+	hooks: Hooks,
+	#[serde(default)]
+	hook_timeout: Option<u64>,
+	#[serde(default = "idle_timeout_default")]
+	idle_timeout: u64,
+	#[serde(default)]
+	retention: Retention,
 }
 
 impl Settings {
@@ -28,10 +66,9 @@ impl Settings {
 		states_dir: Option<PathBuf>,
 		headless: bool,
 		switch_on_add: bool,
-		plugins: Option<Vec<Plugin>>,
+		plugins: Option<Vec<PluginConfig>>,
 	) -> Self {
-		let home_dir =
-			dirs::home_dir().expect("Home couldn't be located in current $PATH variables.");
+		let home_dir = get_home_dir();
 
 		let plugins = plugins.unwrap_or(Default::default());
 
@@ -59,24 +96,74 @@ impl Settings {
 			headless: headless,
 			switch_on_add: switch_on_add,
 			plugins: plugins,
+			env: Default::default(),
+			hooks: Default::default(),
+			hook_timeout: None,
+			idle_timeout: idle_timeout_default(),
+			retention: Default::default(),
 		}
 	}
 
-	/// Returns state-graph source file.
-	pub fn get_save_state(&self) -> Result<PathBuf, SettingsError> {
+	/// Returns settings taken whole from a template, set up in a working
+	/// directory.
+	pub fn from_template(template: Settings, working_dir: Option<PathBuf>, headless: bool) -> Self {
+		let init = Settings::init(
+			working_dir,
+			template.states_dir.clone(),
+			headless,
+			template.switch_on_add,
+			None,
+		);
+
+		Settings {
+			working_dir: init.working_dir,
+			states_dir: init.states_dir,
+			headless: headless,
+			..template
+		}
+	}
+
+	/// Lists state-graph files in the states directory, in any format.
+	pub fn get_state_files(&self) -> Result<Vec<PathBuf>, SettingsError> {
 		if self.headless {
 			return Err(SettingsError::DisallowedHeadless.into());
 		}
 
-		let mut save_state_path = self.states_dir.clone().unwrap_or_default();
+		find_state_files(&self.states_dir.clone().unwrap_or_default())
+	}
+
+	/// Returns state-graph source file. Headless state-graphs live in the
+	/// headless settings file.
+	pub fn get_save_state(&self) -> Result<PathBuf, SettingsError> {
+		if self.headless {
+			return Ok(get_rc_path());
+		}
+
+		let mut state_files = self.get_state_files()?;
 
-		for state_file_path in glob(&(save_state_path.to_string_lossy().into_owned() + "/*.gwsg"))?
-		{
-			return Ok(state_file_path.unwrap_or_else(|_| save_state_path.join("active.gwsg")));
+		if state_files.is_empty() {
+			return Ok(self
+				.states_dir
+				.clone()
+				.unwrap_or_default()
+				.join(STATE_GRAPH_FILE));
 		}
 
-		save_state_path.push("active.gwsg");
-		Ok(save_state_path)
+		Ok(state_files.remove(0))
+	}
+
+	/// Moves working and states directories under a new working directory.
+	pub fn relocate(&mut self, working_dir: PathBuf) -> &mut Self {
+		let prev_working_dir = self.working_dir.clone().unwrap_or_default();
+
+		self.states_dir = self.states_dir.clone().map(|states_dir| {
+			states_dir
+				.strip_prefix(&prev_working_dir)
+				.map(|rel_states_dir| working_dir.join(rel_states_dir))
+				.unwrap_or(states_dir)
+		});
+		self.working_dir = Some(working_dir);
+		self
 	}
 
 	/// Propagates changes to settings.
@@ -90,14 +177,17 @@ impl Settings {
 		let settings_path: PathBuf;
 
 		if self.headless {
-			settings_path = working_dir.join(".gwrc");
+			settings_path = get_rc_path();
 
 			if !upsert {
 				return Err(SettingsError::DisallowedUpsert.into());
 			}
 		} else {
 			let states_dir = &self.states_dir.clone().unwrap_or_default();
-			settings_path = working_dir.join("settings.gwcore");
+			settings_path = match get_config_path() {
+				Some(config_path) if config_path.is_file() => config_path,
+				_ => find_settings_file(working_dir),
+			};
 
 			if !states_dir.exists() {
 				fs::create_dir_all(states_dir)?;
@@ -108,20 +198,30 @@ impl Settings {
 			}
 		}
 
-		File::create(settings_path).and_then(|settings_file| {
-			serde_json::to_writer_pretty(settings_file, &self)?;
-			Ok(())
-		})?;
+		// An embedded state-graph shares the file and must outlive settings
+		// updates.
+		if self.headless && settings_path.exists() {
+			if let Some(state_graph) = read_value(&settings_path)?.get(STATE_GRAPH_KEY) {
+				let mut settings_value = serde_json::to_value(self)?;
+				insert_value(&mut settings_value, STATE_GRAPH_KEY, state_graph.clone());
+				format::write(settings_path, &settings_value)?;
+				return Ok(());
+			}
+		}
+
+		format::write(settings_path, &self)?;
 		Ok(())
 	}
 }
 
+/// Minutes a project may go unseen before its tracked session ends.
+fn idle_timeout_default() -> u64 {
+	30
+}
+
 impl Default for Settings {
 	fn default() -> Self {
-		let home_dir =
-			dirs::home_dir().expect("Home couldn't be located in current $PATH variables.");
-
-		let working_dir = home_dir.join(".godwit");
+		let working_dir = get_working_dir();
 
 		let states_dir = working_dir.join("states");
 
@@ -133,34 +233,191 @@ impl Default for Settings {
 			headless: false,
 			switch_on_add: true,
 			plugins: plugins,
+			env: Default::default(),
+			hooks: Default::default(),
+			hook_timeout: None,
+			idle_timeout: idle_timeout_default(),
+			retention: Default::default(),
 		}
 	}
 }
 
-/// Get settings instance from settings source file.
-pub fn get_settings() -> Result<Settings, SettingsError> {
-	let home_dir = dirs::home_dir().expect("Home couldn't be located in current $PATH variables.");
+/// Sets the working directory or settings file to use over any other source.
+pub fn set_config_path(config_path: Option<PathBuf>) {
+	if let Ok(mut current) = CONFIG_PATH.write() {
+		*current = config_path;
+	}
+}
+
+/// Returns the command line location override.
+fn get_config_path() -> Option<PathBuf> {
+	CONFIG_PATH
+		.read()
+		.ok()
+		.and_then(|config_path| config_path.clone())
+}
+
+/// Returns user home directory.
+pub fn get_home_dir() -> PathBuf {
+	dirs::home_dir().expect("Home couldn't be located in current $PATH variables.")
+}
+
+/// Returns headless settings source file.
+pub fn get_rc_path() -> PathBuf {
+	let rc_path = get_home_dir().join(".gwrc");
+	format::find_variant(&rc_path).unwrap_or(rc_path)
+}
+
+/// Returns the settings file of a working directory, in whichever format it
+/// exists. Defaults to JSON.
+pub fn find_settings_file(working_dir: &Path) -> PathBuf {
+	let settings_path = working_dir.join(SETTINGS_FILE);
+	format::find_variant(&settings_path).unwrap_or(settings_path)
+}
+
+/// Returns Godwit working directory used when nothing overrides it.
+pub fn get_default_working_dir() -> PathBuf {
+	get_home_dir().join(".godwit")
+}
 
-	let working_dir = home_dir.join(".godwit");
+/// Resolves Godwit working directory from `--config`, then `GODWIT_HOME`,
+/// then `.gwrc`, falling back to the default directory.
+pub fn get_working_dir() -> PathBuf {
+	if let Some(config_path) = get_config_path() {
+		if config_path.is_file() {
+			return config_path
+				.parent()
+				.map(Path::to_path_buf)
+				.unwrap_or_default();
+		}
+		return config_path;
+	}
 
-	let rc_path = home_dir.as_path().join(".gwrc");
+	if let Some(godwit_home) = env::var_os(HOME_VAR) {
+		return PathBuf::from(godwit_home);
+	}
 
-	let settings_path = working_dir.join("settings.gwcore");
+	let rc_path = get_rc_path();
 
 	if rc_path.exists() {
-		info!("Godwitrc found at {}", rc_path.display());
-		let settings = File::open(rc_path).and_then(|settings_file| {
-			let settings: Settings = serde_json::from_reader(settings_file)?;
-			Ok(settings)
-		})?;
-		Ok(settings)
-	} else if settings_path.exists() {
-		info!("Settings core found at {}", settings_path.display());
-		let settings = File::open(settings_path).and_then(|settings_file| {
-			let settings: Settings = serde_json::from_reader(settings_file)?;
-			Ok(settings)
-		})?;
-		Ok(settings)
+		// Only the location is read, so that resolution never depends on full
+		// settings being valid.
+		let working_dir = format::read::<Value, _>(&rc_path)
+			.ok()
+			.and_then(|rc| rc.get("working_dir")?.as_str().map(PathBuf::from));
+
+		if let Some(working_dir) = working_dir {
+			return working_dir;
+		}
+	}
+
+	get_default_working_dir()
+}
+
+/// Binds the default working directory location to a working directory
+/// elsewhere through a symlink. Refuses to replace anything but a symlink.
+pub fn bind_working_dir(working_dir: &Path) -> Result<(), SettingsError> {
+	let link = get_default_working_dir();
+
+	if working_dir == link {
+		return Ok(());
+	}
+
+	match fs::symlink_metadata(&link) {
+		Ok(metadata) if metadata.file_type().is_symlink() => {
+			info!(
+				"Unbinding {} from {}.",
+				link.display(),
+				fs::read_link(&link)?.display()
+			);
+			fs::remove_file(&link)?;
+		}
+		Ok(_) => {
+			return Err(SettingsError::WorkingDirOccupied {
+				path: link.to_string_lossy().into_owned(),
+			})
+		}
+		Err(_) => (),
+	}
+
+	info!("Binding {} to {}.", link.display(), working_dir.display());
+
+	#[cfg(unix)]
+	std::os::unix::fs::symlink(working_dir, &link)?;
+	#[cfg(windows)]
+	std::os::windows::fs::symlink_dir(working_dir, &link)?;
+
+	Ok(())
+}
+
+/// Checks whether the default working directory location may be bound to a
+/// working directory.
+pub fn check_bindable(working_dir: &Path) -> Result<(), SettingsError> {
+	let link = get_default_working_dir();
+
+	match fs::symlink_metadata(&link) {
+		Ok(metadata) if working_dir != link && !metadata.file_type().is_symlink() => {
+			Err(SettingsError::WorkingDirOccupied {
+				path: link.to_string_lossy().into_owned(),
+			})
+		}
+		_ => Ok(()),
+	}
+}
+
+/// Detects a working directory link pointing to a missing directory.
+pub fn check_symlink() -> Result<(), SettingsError> {
+	let link = get_default_working_dir();
+
+	let is_symlink = fs::symlink_metadata(&link)
+		.map(|metadata| metadata.file_type().is_symlink())
+		.unwrap_or(false);
+
+	if is_symlink && !link.exists() {
+		return Err(SettingsError::BrokenSymlink {
+			link: link.to_string_lossy().into_owned(),
+			target: fs::read_link(&link)?.to_string_lossy().into_owned(),
+		});
+	}
+
+	Ok(())
+}
+
+/// Resolves settings source file in the same order as the working directory.
+pub fn get_settings_path() -> PathBuf {
+	if let Some(config_path) = get_config_path() {
+		if config_path.is_file() {
+			return config_path;
+		}
+		return find_settings_file(&config_path);
+	}
+
+	let rc_path = get_rc_path();
+
+	if env::var_os(HOME_VAR).is_none() && rc_path.exists() {
+		return rc_path;
+	}
+
+	find_settings_file(&get_working_dir())
+}
+
+/// Returns user settings source file, failing when Godwit isn't set up.
+pub fn get_user_settings_path() -> Result<PathBuf, SettingsError> {
+	let working_dir = get_working_dir();
+
+	let settings_path = get_settings_path();
+
+	if working_dir == get_default_working_dir() {
+		check_symlink()?;
+	}
+
+	if settings_path.exists() {
+		if settings_path == get_rc_path() {
+			info!("Godwitrc found at {}", settings_path.display());
+		} else {
+			info!("Settings core found at {}", settings_path.display());
+		}
+		Ok(settings_path)
 	} else if working_dir.exists() {
 		info!(
 			"No settings files found. Working directory exists at {}.",
@@ -179,29 +436,211 @@ pub fn get_settings() -> Result<Settings, SettingsError> {
 	}
 }
 
-/// Purges settings source file and states.
-pub fn purge_settings(purge_states: bool) -> Result<(), SettingsError> {
-	let home_dir = dirs::home_dir().expect("Home couldn't be located in current $PATH variables.");
+/// Resolves layered settings along with the origin of each value.
+pub fn resolve_settings() -> Result<layers::Resolved, SettingsError> {
+	layers::resolve(&get_user_settings_path()?)
+}
+
+/// Maps a parse error to its location in a settings file.
+fn invalid_settings(path: &Path, e: FormatError) -> SettingsError {
+	match e {
+		FormatError::Parse {
+			line,
+			column,
+			message,
+		} => SettingsError::InvalidSettings {
+			file: path.to_string_lossy().into_owned(),
+			line: line,
+			column: column,
+			message: message,
+		},
+		e => e.into(),
+	}
+}
+
+/// Reads a settings file as a raw value.
+pub fn read_value(path: &Path) -> Result<Value, SettingsError> {
+	format::read(path).map_err(|e| invalid_settings(path, e))
+}
+
+/// Validates a settings file, partial or complete, against the settings
+/// schema.
+pub fn validate_settings(path: &Path) -> Result<Settings, SettingsError> {
+	format::read(path).map_err(|e| invalid_settings(path, e))
+}
 
-	let working_dir = home_dir.join(".godwit");
+/// Returns effective value of a dotted settings key.
+pub fn get_setting(key: &str) -> Result<Value, SettingsError> {
+	layers::lookup(&resolve_settings()?.value, key)
+		.cloned()
+		.ok_or_else(|| SettingsError::UnknownKey {
+			key: key.to_string(),
+		})
+}
 
-	let rc_path = home_dir.join(".gwrc");
+/// Sets a dotted settings key in the user settings. The value is typed after
+/// the current value, falling back to a plain string.
+pub fn set_setting(key: &str, raw_value: &str) -> Result<(), SettingsError> {
+	let field = key.split('.').next().unwrap_or_default();
+
+	if serde_json::to_value(Settings::default())
+		.ok()
+		.and_then(|defaults| defaults.get(field).cloned())
+		.is_none()
+	{
+		return Err(SettingsError::UnknownKey {
+			key: key.to_string(),
+		});
+	}
 
-	let settings_path: PathBuf = working_dir.join("settings.gwcore");
+	let user_path = get_user_settings_path()?;
+	let user_value = read_value(&user_path)?;
 
-	if rc_path.exists() {
+	let typed_value = match layers::lookup(&resolve_settings()?.value, key) {
+		Some(Value::String(_)) => Value::String(raw_value.to_string()),
+		_ => serde_json::from_str(raw_value).unwrap_or(Value::String(raw_value.to_string())),
+	};
+
+	let mut candidates = vec![typed_value];
+	if !candidates[0].is_string() {
+		candidates.push(Value::String(raw_value.to_string()));
+	}
+
+	let mut last_error = None;
+
+	for candidate in candidates {
+		let mut updated = user_value.clone();
+		insert_value(&mut updated, key, candidate);
+
+		match serde_json::from_value::<Settings>(updated.clone()) {
+			Ok(_) => {
+				format::write(&user_path, &updated)?;
+				return Ok(());
+			}
+			Err(e) => last_error = Some(e),
+		}
+	}
+
+	Err(SettingsError::InvalidEffectiveSettings {
+		message: last_error.map(|e| e.to_string()).unwrap_or_default(),
+	})
+}
+
+/// Replaces plugins in the user settings, leaving other keys untouched.
+pub fn save_plugins(plugins: Vec<PluginConfig>) -> Result<(), SettingsError> {
+	let user_path = get_user_settings_path()?;
+	let mut user_value = read_value(&user_path)?;
+
+	insert_value(&mut user_value, "plugins", serde_json::to_value(plugins)?);
+
+	format::write(&user_path, &user_value)?;
+	Ok(())
+}
+
+/// Inserts a value at a dotted key, creating intermediate objects.
+fn insert_value(target: &mut Value, key: &str, value: Value) {
+	let mut current = target;
+
+	for segment in key.split('.') {
+		if !current.is_object() {
+			*current = Value::Object(Default::default());
+		}
+
+		current = current
+			.as_object_mut()
+			.map(|map| map.entry(segment).or_insert(Value::Null))
+			.expect("Value was just made an object.");
+	}
+
+	*current = value;
+}
+
+/// Get settings instance from the user settings source alone. Use this over
+/// `get_settings` when changes are to be saved back.
+pub fn get_user_settings() -> Result<Settings, SettingsError> {
+	validate_settings(&get_user_settings_path()?)
+}
+
+/// Get effective settings instance from layered settings sources.
+pub fn get_settings() -> Result<Settings, SettingsError> {
+	resolve_settings()?.settings()
+}
+
+/// Rewrites the user settings file in another format. Returns the new path.
+pub fn convert_settings(target_format: Format) -> Result<PathBuf, SettingsError> {
+	let settings_path = get_user_settings_path()?;
+	let converted_path = format::convert(&settings_path, target_format)?;
+
+	if get_config_path() == Some(settings_path) {
+		set_config_path(Some(converted_path.clone()));
+	}
+
+	Ok(converted_path)
+}
+
+/// Lists state-graph files in a states directory, in any format.
+pub fn find_state_files(states_dir: &Path) -> Result<Vec<PathBuf>, SettingsError> {
+	let pattern = states_dir.to_string_lossy().into_owned();
+
+	let mut state_files = Vec::new();
+
+	for state_file_path in glob(&(pattern + "/*.gwsg*"))?.flatten() {
+		// Only bare names and known format extensions, leaving backups and
+		// swap files out.
+		if format::strip_format(&state_file_path).extension() == Some("gwsg".as_ref()) {
+			state_files.push(state_file_path);
+		}
+	}
+
+	Ok(state_files)
+}
+
+/// Purges the settings file and, optionally, state-graph files. Only files
+/// Godwit owns are removed, leaving sessions, activity, logs, templates,
+/// scaffolds and secrets in place. A directory without Godwit settings is
+/// never purged.
+pub fn purge_settings(purge_states: bool) -> Result<(), SettingsError> {
+	let working_dir = get_working_dir();
+
+	let rc_path = get_rc_path();
+
+	let settings_path = get_settings_path();
+
+	if settings_path == rc_path && rc_path.exists() {
 		info!("Purging {}", rc_path.display());
 		fs::remove_file(rc_path)?;
-	} else if working_dir.exists() {
-		if purge_states {
-			info!("Purging {}.", working_dir.display());
-			fs::remove_dir_all(working_dir)?;
-		} else {
-			info!("Purging {}.", settings_path.display());
-			fs::remove_file(settings_path)?;
-		}
-	} else {
+		return Ok(());
+	}
+
+	if !working_dir.exists() {
 		return Err(SettingsError::WorkingDirNotFound.into());
 	}
+
+	if !settings_path.is_file() {
+		return Err(SettingsError::PurgeRefused {
+			path: working_dir.to_string_lossy().into_owned(),
+		});
+	}
+
+	if purge_states {
+		let states_dir = get_settings()
+			.ok()
+			.and_then(|settings| settings.get_states_dir())
+			.unwrap_or_else(|| working_dir.join("states"));
+
+		for state_file in find_state_files(&states_dir)? {
+			info!("Purging {}.", state_file.display());
+			fs::remove_file(state_file)?;
+		}
+
+		// Left in place unless emptied.
+		if states_dir.starts_with(&working_dir) {
+			let _ = fs::remove_dir(&states_dir);
+		}
+	}
+
+	info!("Purging {}.", settings_path.display());
+	fs::remove_file(settings_path)?;
+
 	Ok(())
 }
@@ -0,0 +1,80 @@
+//! Project Settings Trust
+//!
+//! Project settings come with whatever directory Godwit runs from, so they
+//! may only set fields that run commands or redirect writes once trusted, much like direnv. Trust is
+//! given to a file as it stands: any later change revokes it until trusted
+//! again. Trusted files are kept with their digest in the working directory.
+use crate::errors::SettingsError;
+use crate::settings;
+use ring::digest::{self, SHA256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Trusted project settings file name inside a working directory.
+pub const TRUST_FILE: &str = "trusted.json";
+
+/// Settings fields that run commands, reach the shell or redirect where
+/// Godwit writes.
+pub const GATED_FIELDS: &[&str] = &["hooks", "plugins", "env", "working_dir", "states_dir"];
+
+/// Returns the trusted project settings file.
+fn get_trust_path() -> PathBuf {
+	settings::get_working_dir().join(TRUST_FILE)
+}
+
+/// Loads trusted project settings along with their digest.
+fn load_trusted() -> Result<BTreeMap<PathBuf, String>, SettingsError> {
+	match fs::read_to_string(get_trust_path()) {
+		Ok(content) => Ok(serde_json::from_str(&content)?),
+		Err(_) => Ok(BTreeMap::new()),
+	}
+}
+
+/// Saves trusted project settings along with their digest.
+fn save_trusted(trusted: &BTreeMap<PathBuf, String>) -> Result<(), SettingsError> {
+	fs::write(get_trust_path(), serde_json::to_string_pretty(trusted)?)?;
+	Ok(())
+}
+
+/// Returns the digest of a settings file.
+fn file_digest(path: &Path) -> Result<String, SettingsError> {
+	let content = fs::read(path)?;
+	Ok(base64::encode(digest::digest(&SHA256, &content).as_ref()))
+}
+
+/// Checks whether a project settings file is trusted as it stands.
+pub fn is_trusted(path: &Path) -> bool {
+	let path = match path.canonicalize() {
+		Ok(path) => path,
+		Err(_) => return false,
+	};
+
+	match (load_trusted(), file_digest(&path)) {
+		(Ok(trusted), Ok(digest)) => trusted.get(&path) == Some(&digest),
+		_ => false,
+	}
+}
+
+/// Trusts a project settings file as it stands. Returns its full path.
+pub fn trust(path: &Path) -> Result<PathBuf, SettingsError> {
+	let path = path.canonicalize()?;
+	let digest = file_digest(&path)?;
+
+	let mut trusted = load_trusted()?;
+	trusted.insert(path.clone(), digest);
+	save_trusted(&trusted)?;
+
+	Ok(path)
+}
+
+/// Revokes trust in a project settings file. Returns its full path.
+pub fn revoke(path: &Path) -> Result<PathBuf, SettingsError> {
+	let path = path.canonicalize()?;
+
+	let mut trusted = load_trusted()?;
+	trusted.remove(&path);
+	save_trusted(&trusted)?;
+
+	Ok(path)
+}
@@ -1,14 +1,18 @@
 //! Godwit State Handler
 //!
 //! A core state management utility for context switching and global singletons.
-use crate::env::{set_env_var, Var};
-use crate::errors::StateError;
+use crate::env::{self, set_env_var, unset_env_var, Var};
+use crate::errors::{FormatError, StateError};
+use crate::format::{self, Format};
 use crate::glyph::Glyph;
+use crate::hooks::Hooks;
 use crate::settings;
+use crate::tracker;
 use getter_derive::Getter;
 use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::path::PathBuf;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 #[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub enum Status {
@@ -27,6 +31,10 @@ pub struct State {
 	glyph: Glyph,
 	directory: Option<PathBuf>,
 	status: Option<Vec<Status>>,
+	#[serde(default)]
+	env: BTreeMap<String, String>,
+	#[serde(default, skip_serializing_if = "Hooks::is_empty")]
+	hooks: Hooks,
 }
 
 impl Default for State {
@@ -35,6 +43,8 @@ impl Default for State {
 			glyph: Default::default(),
 			directory: Default::default(),
 			status: Default::default(),
+			env: Default::default(),
+			hooks: Default::default(),
 		}
 	}
 }
@@ -94,6 +104,21 @@ impl StateGraph {
 		self.states.retain(|p_state| p_state != state);
 		self
 	}
+
+	/// Replaces every copy of a state, matched by glyph, with its new version.
+	pub fn update_state(&mut self, state: State) -> &mut Self {
+		for p_state in self
+			.states
+			.iter_mut()
+			.chain(self.active.iter_mut())
+			.chain(self.default.iter_mut())
+		{
+			if p_state.glyph == state.glyph {
+				*p_state = state.clone();
+			}
+		}
+		self
+	}
 	/// Searches state by glyph and directory
 	pub fn search_states(&self, q_term: String, fuzzy: bool) -> Option<State> {
 		for state in &self.states {
@@ -122,12 +147,56 @@ impl StateGraph {
 		None
 	}
 
-	/// Commits changes to state-graph file.
+	/// Checks that glyphs are unique and that the active and default states
+	/// are listed.
+	pub fn validate(&self) -> Result<(), StateError> {
+		for (idx, state) in self.states.iter().enumerate() {
+			if self.states[..idx]
+				.iter()
+				.any(|p_state| p_state.glyph == state.glyph)
+			{
+				return Err(StateError::StateAlreadyExists {
+					state: state.glyph.to_string(),
+				});
+			}
+		}
+
+		for state in self.active.iter().chain(self.default.iter()) {
+			if !self
+				.states
+				.iter()
+				.any(|p_state| p_state.glyph == state.glyph)
+			{
+				return Err(StateError::StateNotFound {
+					state: state.glyph.to_string(),
+				});
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Commits changes to state-graph file. Headless state-graphs are
+	/// embedded in the headless settings file.
 	pub fn propagate(&self) -> Result<(), StateError> {
-		File::create(settings::get_settings()?.get_save_state()?).and_then(|state_file| {
-			serde_json::to_writer_pretty(state_file, &self)?;
-			Ok(())
-		})?;
+		let settings = settings::get_settings()?;
+		let state_path = settings.get_save_state()?;
+
+		if settings.get_headless() {
+			let mut rc: Value = format::read(&state_path)?;
+
+			if let Some(rc_map) = rc.as_object_mut() {
+				rc_map.insert(
+					settings::STATE_GRAPH_KEY.to_string(),
+					serde_json::to_value(self)?,
+				);
+			}
+
+			format::write(&state_path, &rc)?;
+		} else {
+			format::write(&state_path, &self)?;
+		}
+
 		Ok(())
 	}
 }
@@ -143,14 +212,67 @@ impl Default for StateGraph {
 	}
 }
 
+/// Maps a parse error to its location in a state-graph file.
+fn invalid_stategraph(path: &Path, e: FormatError) -> StateError {
+	match e {
+		FormatError::Parse {
+			line,
+			column,
+			message,
+		} => StateError::InvalidStateGraph {
+			file: path.to_string_lossy().into_owned(),
+			line: line,
+			column: column,
+			message: message,
+		},
+		FormatError::IO { source } => source.into(),
+		e => e.into(),
+	}
+}
+
 /// Returns a state-graph instance from the state-graph file.
 pub fn load_stategraph() -> Result<StateGraph, StateError> {
-	let state_graph =
-		File::open(settings::get_settings()?.get_save_state()?).and_then(|state_file| {
-			let state_graph: StateGraph = serde_json::from_reader(state_file)?;
-			Ok(state_graph)
+	let settings = settings::get_settings()?;
+	let state_path = settings.get_save_state()?;
+
+	if settings.get_headless() {
+		return load_embedded_stategraph(&state_path);
+	}
+
+	format::read(&state_path).map_err(|e| invalid_stategraph(&state_path, e))
+}
+
+/// Returns the state-graph embedded in headless settings.
+fn load_embedded_stategraph(rc_path: &Path) -> Result<StateGraph, StateError> {
+	let mut rc: Value = format::read(rc_path).map_err(|e| invalid_stategraph(rc_path, e))?;
+
+	let embedded = rc
+		.get_mut(settings::STATE_GRAPH_KEY)
+		.map(Value::take)
+		.ok_or_else(|| StateError::StateGraphNotFound {
+			file: rc_path.to_string_lossy().into_owned(),
 		})?;
-	Ok(state_graph)
+
+	serde_json::from_value(embedded).map_err(|e| StateError::InvalidEmbeddedStateGraph {
+		file: rc_path.to_string_lossy().into_owned(),
+		message: e.to_string(),
+	})
+}
+
+/// Prints environment changes as shell commands in headless usage, which
+/// leaves shell configuration files alone.
+fn bind_env_output() -> Result<(), StateError> {
+	env::set_export_only(settings::get_settings()?.get_headless());
+	Ok(())
+}
+
+/// Rewrites state-graph files in another format. Returns the new paths.
+pub fn convert_stategraphs(target_format: Format) -> Result<Vec<PathBuf>, StateError> {
+	settings::get_settings()?
+		.get_state_files()?
+		.into_iter()
+		.map(|state_path| Ok(format::convert(&state_path, target_format)?))
+		.collect()
 }
 
 /// Sets the active state in state-graph and propagates it.
@@ -167,18 +289,118 @@ pub fn set_active(q_glyph: Glyph) -> Result<(), StateError> {
 				.into())
 			},
 			|q_state| {
-				sg_snapshot.active(q_state.clone()).propagate()?;
+				let prev_state = sg_snapshot.get_active();
 
-				set_env_var(
-					Var::GPD,
-					q_state.directory.clone().unwrap().to_str().unwrap(),
-				)?;
+				sg_snapshot.active(q_state.clone()).propagate()?;
 
-				Ok(())
+				bind_env_output()?;
+				activate(prev_state.as_ref(), &q_state)
 			},
 		)
 }
 
+/// Exports the directory and environment of a newly active state and tracks
+/// it, unsetting variables left over from the previously active one.
+fn activate(prev_state: Option<&State>, state: &State) -> Result<(), StateError> {
+	let mut vars = Vec::new();
+
+	if let Some(directory) = state.directory.as_ref() {
+		vars.push((Var::GPD, Some(directory.to_string_lossy().into_owned())));
+	}
+
+	vars.extend(apply_env(prev_state, state)?);
+	env::set_env_vars(&vars)?;
+
+	tracker::track(&state.glyph);
+
+	Ok(())
+}
+
+/// Returns the environment of a state layered over the settings environment.
+pub fn get_state_env(state: &State) -> Result<BTreeMap<String, String>, StateError> {
+	let mut env = settings::get_settings()?.get_env();
+	env.extend(state.env.clone());
+	Ok(env)
+}
+
+/// Returns the settings and state environment of an activated state, along
+/// with variables left over from the previously active one to unset.
+fn apply_env(
+	prev_state: Option<&State>,
+	state: &State,
+) -> Result<Vec<(Var, Option<String>)>, StateError> {
+	let env = get_state_env(state)?;
+	let mut vars = Vec::new();
+
+	if let Some(prev_state) = prev_state {
+		for key in prev_state.env.keys() {
+			if !env.contains_key(key) {
+				vars.push((Var::Custom(key.clone()), None));
+			}
+		}
+	}
+
+	for (key, value) in env {
+		vars.push((Var::Custom(key), Some(value)));
+	}
+
+	Ok(vars)
+}
+
+/// Returns the environment of a state that is no longer active to unset,
+/// restoring settings values it overrode.
+fn clear_env(state: &State) -> Result<Vec<(Var, Option<String>)>, StateError> {
+	let mut settings_env = settings::get_settings()?.get_env();
+
+	let mut vars = state
+		.env
+		.keys()
+		.map(|key| (Var::Custom(key.clone()), settings_env.remove(key)))
+		.collect::<Vec<_>>();
+
+	vars.push((Var::GPD, None));
+	Ok(vars)
+}
+
+/// Sets or clears a variable in the environment of a state and propagates it.
+pub fn set_state_env(q_glyph: Glyph, key: String, value: Option<String>) -> Result<(), StateError> {
+	let mut sg_snapshot: StateGraph = load_stategraph()?;
+
+	let mut q_state = sg_snapshot
+		.search_states(q_glyph.to_string(), false)
+		.ok_or_else(|| StateError::StateNotFound {
+			state: q_glyph.clone().into(),
+		})?;
+
+	match value.clone() {
+		Some(value) => q_state.env.insert(key.clone(), value),
+		None => q_state.env.remove(&key),
+	};
+
+	sg_snapshot.update_state(q_state.clone()).propagate()?;
+
+	if sg_snapshot
+		.get_active()
+		.map_or(false, |active| active.glyph == q_glyph)
+	{
+		bind_env_output()?;
+
+		match value.or_else(|| settings::get_settings().ok()?.get_env().remove(&key)) {
+			Some(value) => set_env_var(Var::Custom(key), value)?,
+			None => unset_env_var(Var::Custom(key))?,
+		}
+	}
+
+	Ok(())
+}
+
+/// Returns the active state.
+pub fn get_active() -> Result<State, StateError> {
+	load_stategraph()?
+		.get_active()
+		.ok_or(StateError::NoActiveState)
+}
+
 /// Sets the default state in state-graph and propagates it.
 pub fn set_default(q_glyph: Glyph) -> Result<(), StateError> {
 	let mut sg_snapshot: StateGraph = load_stategraph()?;
@@ -204,6 +426,7 @@ pub fn add_state(
 	glyph: Glyph,
 	location: PathBuf,
 	status: Option<Vec<Status>>,
+	env: BTreeMap<String, String>,
 	as_active: bool,
 	as_default: bool,
 ) -> Result<(), StateError> {
@@ -220,6 +443,8 @@ pub fn add_state(
 			glyph: glyph.clone(),
 			directory: Some(location),
 			status: status,
+			env: env,
+			hooks: Default::default(),
 		};
 
 		let mut sg_snapshot = sg_snapshot.append_state(new_state.clone());
@@ -263,12 +488,26 @@ pub fn purge_state(q_glyph: Glyph) -> Result<(), StateError> {
 					sg_snapshot = sg_snapshot.fallback(sg_snapshot.get_states().clone().remove(0));
 				}
 
-				if q_state == sg_snapshot.get_active().unwrap_or_default() {
+				let was_active = q_state == sg_snapshot.get_active().unwrap_or_default();
+
+				if was_active {
 					sg_snapshot = sg_snapshot.active(sg_snapshot.get_default().unwrap_or_default());
 				}
 
 				sg_snapshot.propagate()?;
 
+				if was_active {
+					bind_env_output()?;
+
+					match sg_snapshot
+						.get_active()
+						.filter(|active_state| active_state.directory.is_some())
+					{
+						Some(active_state) => activate(Some(&q_state), &active_state)?,
+						None => env::set_env_vars(&clear_env(&q_state)?)?,
+					}
+				}
+
 				Ok(())
 			},
 		)
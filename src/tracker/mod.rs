@@ -0,0 +1,399 @@
+//! Time Tracking
+//!
+//! Records sessions of time spent on projects. A session opens when a project
+//! becomes active and closes when another one does, or once the project has
+//! gone unseen for longer than the idle timeout, in which case it ends when
+//! the project was last seen. Sessions are stored per glyph under `sessions/`
+//! in the working directory, along with a pointer to the project whose
+//! session is open. Tracking is serialized by a lock file, so that a switch
+//! and a heartbeat never interleave. Nothing is tracked in headless mode.
+use crate::errors::TrackerError;
+use crate::glyph::Glyph;
+use crate::settings;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone};
+use getter_derive::Getter;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// File naming the project whose session is open.
+pub const OPEN_FILE: &str = "open";
+
+/// Lock file serializing tracking.
+pub const LOCK_FILE: &str = "sessions.lock";
+
+/// Tracked time spent on a project.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Getter)]
+#[serde(rename_all = "snake_case")]
+pub struct Session {
+	glyph: Glyph,
+	started: DateTime<Local>,
+	seen: DateTime<Local>,
+	ended: Option<DateTime<Local>>,
+}
+
+impl Session {
+	/// Opens a session on a project.
+	fn open(glyph: &Glyph, now: DateTime<Local>) -> Self {
+		Session {
+			glyph: glyph.clone(),
+			started: now,
+			seen: now,
+			ended: None,
+		}
+	}
+
+	/// Checks whether an open session went unseen for too long.
+	fn is_idle(&self, now: DateTime<Local>, idle_timeout: Duration) -> bool {
+		now - self.seen > idle_timeout
+	}
+
+	/// Returns the end of a session, open ones ending now unless idle.
+	pub fn end(&self, now: DateTime<Local>, idle_timeout: Duration) -> DateTime<Local> {
+		match self.ended {
+			Some(ended) => ended,
+			None if self.is_idle(now, idle_timeout) => self.seen,
+			None => now,
+		}
+	}
+}
+
+/// Period sessions are grouped by in reports.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Period {
+	Day,
+	Week,
+}
+
+impl Period {
+	/// Returns the first day of the period holding a date.
+	pub fn start(&self, date: NaiveDate) -> NaiveDate {
+		match self {
+			Period::Day => date,
+			Period::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+		}
+	}
+
+	/// Returns the first day of the next period.
+	fn next(&self, date: NaiveDate) -> NaiveDate {
+		match self {
+			Period::Day => self.start(date) + Duration::days(1),
+			Period::Week => self.start(date) + Duration::weeks(1),
+		}
+	}
+}
+
+impl fmt::Display for Period {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Period::Day => write!(f, "day"),
+			Period::Week => write!(f, "week"),
+		}
+	}
+}
+
+impl FromStr for Period {
+	type Err = TrackerError;
+
+	fn from_str(period_str: &str) -> Result<Self, Self::Err> {
+		match period_str.trim().to_lowercase().as_str() {
+			"day" => Ok(Period::Day),
+			"week" => Ok(Period::Week),
+			_ => Err(TrackerError::UnknownPeriod {
+				period: period_str.into(),
+			}),
+		}
+	}
+}
+
+/// Time spent on a project, within a period when grouped.
+#[derive(Clone, Debug, PartialEq, Getter)]
+pub struct Row {
+	glyph: Glyph,
+	period: Option<NaiveDate>,
+	duration: Duration,
+}
+
+/// Returns the sessions directory.
+pub fn get_sessions_dir() -> PathBuf {
+	settings::get_working_dir().join("sessions")
+}
+
+/// Returns the sessions file of a project.
+fn sessions_path(glyph: &Glyph) -> PathBuf {
+	get_sessions_dir()
+		.join(&glyph.tag)
+		.join(format!("{}.json", glyph.id))
+}
+
+/// Returns the idle timeout from settings.
+fn idle_timeout() -> Result<Duration, TrackerError> {
+	Ok(Duration::minutes(
+		settings::get_settings()?.get_idle_timeout() as i64,
+	))
+}
+
+/// Lists projects with recorded sessions.
+pub fn tracked_glyphs() -> Result<Vec<Glyph>, TrackerError> {
+	let mut glyphs = Vec::new();
+
+	let tag_dirs = match fs::read_dir(get_sessions_dir()) {
+		Ok(tag_dirs) => tag_dirs,
+		Err(_) => return Ok(glyphs),
+	};
+
+	for tag_dir in tag_dirs
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| entry.path().is_dir())
+	{
+		let tag = tag_dir.file_name().to_string_lossy().into_owned();
+
+		for path in fs::read_dir(tag_dir.path())?
+			.filter_map(|entry| entry.ok())
+			.map(|entry| entry.path())
+		{
+			if let Some(id) = path
+				.file_stem()
+				.filter(|_| path.extension().map_or(false, |ext| ext == "json"))
+			{
+				glyphs.push(Glyph {
+					tag: tag.clone(),
+					id: id.to_string_lossy().into_owned(),
+				});
+			}
+		}
+	}
+
+	glyphs.sort_by_key(Glyph::to_string);
+	Ok(glyphs)
+}
+
+/// Loads the sessions of a project, oldest first.
+pub fn load_sessions(glyph: &Glyph) -> Result<Vec<Session>, TrackerError> {
+	match fs::read_to_string(sessions_path(glyph)) {
+		Ok(content) => Ok(serde_json::from_str(&content)?),
+		Err(_) => Ok(Vec::new()),
+	}
+}
+
+/// Saves the sessions of a project.
+pub fn save_sessions(glyph: &Glyph, sessions: &[Session]) -> Result<(), TrackerError> {
+	let path = sessions_path(glyph);
+
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)?;
+	}
+
+	fs::write(path, serde_json::to_string_pretty(sessions)?)?;
+	Ok(())
+}
+
+/// Returns the project whose session is open, if known.
+fn load_open() -> Option<Glyph> {
+	fs::read_to_string(get_sessions_dir().join(OPEN_FILE))
+		.ok()
+		.and_then(|glyph_str| glyph_str.trim().parse().ok())
+}
+
+/// Points to the project whose session is open.
+fn save_open(glyph: &Glyph) -> Result<(), TrackerError> {
+	let path = get_sessions_dir().join(OPEN_FILE);
+	let swap_path = path.with_extension("swap");
+
+	fs::create_dir_all(get_sessions_dir())?;
+	fs::write(&swap_path, glyph.to_string())?;
+	fs::rename(swap_path, path)?;
+	Ok(())
+}
+
+/// Locks tracking, waiting for other holders. Released once dropped.
+fn lock() -> Result<fs::File, TrackerError> {
+	fs::create_dir_all(get_sessions_dir())?;
+
+	let file = OpenOptions::new()
+		.create(true)
+		.write(true)
+		.truncate(false)
+		.open(get_sessions_dir().join(LOCK_FILE))?;
+
+	#[cfg(unix)]
+	{
+		use std::os::unix::io::AsRawFd;
+
+		if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+			return Err(std::io::Error::last_os_error().into());
+		}
+	}
+
+	Ok(file)
+}
+
+/// Closes the open session of a project, if any.
+fn close(glyph: &Glyph, now: DateTime<Local>, idle_timeout: Duration) -> Result<(), TrackerError> {
+	let mut sessions = load_sessions(glyph)?;
+
+	if let Some(session) = sessions
+		.last_mut()
+		.filter(|session| session.ended.is_none())
+	{
+		debug!("Closing session of {}.", glyph);
+		session.ended = Some(session.end(now, idle_timeout));
+		save_sessions(glyph, &sessions)?;
+	}
+
+	Ok(())
+}
+
+/// Closes the open session of any other project. Without a pointer to it,
+/// as left by earlier versions, every project is looked at.
+fn close_others(glyph: &Glyph, now: DateTime<Local>) -> Result<(), TrackerError> {
+	let idle_timeout = idle_timeout()?;
+
+	let others = match load_open() {
+		Some(open) => vec![open],
+		None => tracked_glyphs()?,
+	};
+
+	for other in others.into_iter().filter(|other| other != glyph) {
+		close(&other, now, idle_timeout)?;
+	}
+
+	Ok(())
+}
+
+/// Marks a project seen, opening a session unless one is open and recent.
+/// An idle open session is closed when it was last seen.
+fn see(glyph: &Glyph, now: DateTime<Local>) -> Result<(), TrackerError> {
+	let idle_timeout = idle_timeout()?;
+	let mut sessions = load_sessions(glyph)?;
+
+	match sessions
+		.last_mut()
+		.filter(|session| session.ended.is_none())
+	{
+		Some(session) if !session.is_idle(now, idle_timeout) => session.seen = now,
+		Some(session) => {
+			session.ended = Some(session.seen);
+			sessions.push(Session::open(glyph, now));
+		}
+		None => sessions.push(Session::open(glyph, now)),
+	}
+
+	save_sessions(glyph, &sessions)
+}
+
+/// Opens a session on a newly active project, closing others.
+pub fn start(glyph: &Glyph) -> Result<(), TrackerError> {
+	let _lock = lock()?;
+	let now = Local::now();
+
+	close_others(glyph, now)?;
+	see(glyph, now)?;
+	save_open(glyph)
+}
+
+/// Marks the active project seen, keeping its session open. Projects
+/// switched away from meanwhile are left alone.
+pub fn touch(glyph: &Glyph) -> Result<(), TrackerError> {
+	let _lock = lock()?;
+
+	match load_open() {
+		Some(open) if &open != glyph => {
+			debug!("Not tracking {}, as {} is open.", glyph, open);
+			Ok(())
+		}
+		_ => see(glyph, Local::now()),
+	}
+}
+
+/// Checks whether sessions are tracked.
+fn is_tracking() -> bool {
+	settings::get_settings().map_or(false, |settings| !settings.get_headless())
+}
+
+/// Tracks a project becoming active. Failing to do so never fails the switch.
+pub fn track(glyph: &Glyph) {
+	if !is_tracking() {
+		return;
+	}
+
+	if let Err(e) = start(glyph) {
+		warn!("Session of {} couldn't be tracked: {}", glyph, e);
+	}
+}
+
+/// Tracks the active project being used. Failing to do so is only warned.
+pub fn heartbeat(glyph: &Glyph) {
+	if !is_tracking() {
+		return;
+	}
+
+	if let Err(e) = touch(glyph) {
+		warn!("Session of {} couldn't be tracked: {}", glyph, e);
+	}
+}
+
+/// Returns the local start of a day.
+fn midnight(date: NaiveDate) -> DateTime<Local> {
+	let naive = date.and_hms_opt(0, 0, 0).unwrap(); // Midnight always exists
+
+	Local
+		.from_local_datetime(&naive)
+		.earliest()
+		.unwrap_or_else(|| Local.from_utc_datetime(&naive))
+}
+
+/// Reports time spent per project since a day, split by period when given.
+pub fn report(
+	glyph: Option<Glyph>,
+	since: Option<NaiveDate>,
+	by: Option<Period>,
+) -> Result<Vec<Row>, TrackerError> {
+	let now = Local::now();
+	let idle_timeout = idle_timeout()?;
+	let since = since.map(midnight);
+
+	let glyphs = match glyph {
+		Some(glyph) => vec![glyph],
+		None => tracked_glyphs()?,
+	};
+
+	let mut rows = Vec::new();
+
+	for glyph in glyphs {
+		let mut totals: BTreeMap<Option<NaiveDate>, Duration> = BTreeMap::new();
+
+		for session in load_sessions(&glyph)? {
+			let end = session.end(now, idle_timeout);
+			let mut start = match since {
+				Some(since) if since > session.started => since,
+				_ => session.started,
+			};
+
+			while start < end {
+				let (period, until) = match by {
+					Some(by) => {
+						let date = start.naive_local().date();
+						(Some(by.start(date)), midnight(by.next(date)).min(end))
+					}
+					None => (None, end),
+				};
+
+				*totals.entry(period).or_insert_with(Duration::zero) += until - start;
+				start = until;
+			}
+		}
+
+		rows.extend(totals.into_iter().map(|(period, duration)| Row {
+			glyph: glyph.clone(),
+			period: period,
+			duration: duration,
+		}));
+	}
+
+	Ok(rows)
+}
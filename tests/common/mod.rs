@@ -0,0 +1,24 @@
+//! Shared Test Helpers
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+
+/// Guards the test working directory setup.
+static ISOLATE: Once = Once::new();
+
+/// Points Godwit at an empty working directory, so that tests never touch
+/// real settings and nothing gets recorded.
+pub fn isolate() {
+	ISOLATE.call_once(|| {
+		let working_dir = Box::leak(Box::new(tempfile::tempdir().unwrap()));
+		env::set_var("GODWIT_HOME", working_dir.path());
+	});
+}
+
+/// Returns the path of a test fixture.
+pub fn fixture(name: &str) -> PathBuf {
+	Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("tests")
+		.join("fixtures")
+		.join(name)
+}
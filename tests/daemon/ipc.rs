@@ -0,0 +1,191 @@
+//! Daemon IPC against a served socket.
+use crate::common::isolate;
+use godwit::daemon::ipc::{self, Client, Request, Response};
+use godwit::daemon::{self, Notification};
+use godwit::errors::DaemonError;
+use godwit::glyph::Glyph;
+use godwit::statehandler::State;
+use serde_json::json;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use tempfile::TempDir;
+
+/// Answers requests the way the daemon would.
+fn answer(request: &Request) -> Response {
+	match request {
+		Request::Ping => Response::Pong {
+			version: String::from("test"),
+		},
+		Request::StateGraph => Response::StateGraph {
+			state_graph: Default::default(),
+		},
+		Request::Notify { .. } | Request::Subscribe => Response::Ack,
+	}
+}
+
+/// Returns the glyph of the test project.
+fn glyph() -> Glyph {
+	"@org/svc".parse().unwrap()
+}
+
+/// Returns the state of the test project.
+fn state() -> State {
+	serde_json::from_value(json!({
+		"glyph": "@org/svc",
+		"directory": "/home/me/svc",
+		"status": null,
+	}))
+	.unwrap()
+}
+
+/// Serves the test handler on a socket in the background.
+fn serve_at(socket_path: &Path) {
+	let listener = UnixListener::bind(socket_path).unwrap();
+	thread::spawn(move || ipc::serve(listener, answer));
+}
+
+/// Serves the test handler on a socket in a new directory.
+fn serve() -> (TempDir, PathBuf) {
+	let dir = tempfile::tempdir().unwrap();
+	let socket_path = dir.path().join(ipc::SOCKET_FILE);
+	serve_at(&socket_path);
+	(dir, socket_path)
+}
+
+/// Listens on a socket in a new directory, handing the first connection to
+/// a raw handler.
+fn listen<F>(handler: F) -> (TempDir, PathBuf)
+where
+	F: FnOnce(BufReader<UnixStream>) + Send + 'static,
+{
+	let dir = tempfile::tempdir().unwrap();
+	let socket_path = dir.path().join(ipc::SOCKET_FILE);
+	let listener = UnixListener::bind(&socket_path).unwrap();
+
+	thread::spawn(move || {
+		let (stream, _) = listener.accept().unwrap();
+		handler(BufReader::new(stream));
+	});
+
+	(dir, socket_path)
+}
+
+#[test]
+fn query_answers_every_request() {
+	isolate();
+
+	let socket_path = ipc::get_socket_path();
+	fs::create_dir_all(socket_path.parent().unwrap()).unwrap();
+	serve_at(&socket_path);
+
+	match ipc::query(&Request::Ping).unwrap() {
+		Response::Pong { version } => assert_eq!(version, "test"),
+		response => panic!("unexpected {:?}", response),
+	}
+	match ipc::query(&Request::StateGraph).unwrap() {
+		Response::StateGraph { state_graph } => assert!(state_graph.get_states().is_empty()),
+		response => panic!("unexpected {:?}", response),
+	}
+	match ipc::query(&Request::Notify {
+		notification: Notification::new("add", &state(), None),
+	})
+	.unwrap()
+	{
+		Response::Ack => (),
+		response => panic!("unexpected {:?}", response),
+	}
+	match ipc::query(&Request::Subscribe).unwrap() {
+		Response::Ack => (),
+		response => panic!("unexpected {:?}", response),
+	}
+
+	assert!(daemon::state_graph().is_none());
+
+	let pid_path = daemon::get_daemon_dir().join(daemon::PID_FILE);
+	fs::write(&pid_path, std::process::id().to_string()).unwrap();
+	assert!(daemon::state_graph().is_some());
+	fs::remove_file(&pid_path).unwrap();
+}
+
+#[test]
+fn error_responses_map_to_remote_errors() {
+	let (_dir, socket_path) = listen(|mut reader| {
+		let mut line = String::new();
+		reader.read_line(&mut line).unwrap();
+		reader
+			.get_mut()
+			.write_all(b"{\"response\":\"error\",\"message\":\"no state-graph\"}\n")
+			.unwrap();
+	});
+
+	let result = Client::connect_to(&socket_path)
+		.unwrap()
+		.request(&Request::StateGraph);
+
+	match result {
+		Err(DaemonError::Remote { message }) => assert_eq!(message, "no state-graph"),
+		result => panic!("unexpected {:?}", result.map(|_| ())),
+	}
+}
+
+#[test]
+fn subscriptions_receive_notified_events() {
+	let (_dir, socket_path) = serve();
+
+	let mut subscription = Client::connect_to(&socket_path)
+		.unwrap()
+		.subscribe()
+		.unwrap();
+
+	let response = Client::connect_to(&socket_path)
+		.unwrap()
+		.request(&Request::Notify {
+			notification: Notification::new("switch", &state(), Some(glyph())),
+		})
+		.unwrap();
+	assert!(matches!(response, Response::Ack));
+
+	let notification = subscription.next().unwrap().unwrap();
+	assert_eq!(notification.get_event(), "switch");
+	assert_eq!(notification.get_active(), Some(glyph()));
+}
+
+#[test]
+fn requests_fail_once_the_connection_closes() {
+	let (_dir, socket_path) = listen(|mut reader| {
+		let mut line = String::new();
+		reader.read_line(&mut line).unwrap();
+	});
+
+	match Client::connect_to(&socket_path)
+		.unwrap()
+		.request(&Request::Ping)
+	{
+		Err(DaemonError::Protocol { message }) => {
+			assert_eq!(message, "the connection was closed")
+		}
+		result => panic!("unexpected {:?}", result.map(|_| ())),
+	}
+}
+
+#[test]
+fn subscriptions_end_once_the_connection_closes() {
+	let (_dir, socket_path) = listen(|mut reader| {
+		let mut line = String::new();
+		reader.read_line(&mut line).unwrap();
+		reader
+			.get_mut()
+			.write_all(b"{\"response\":\"ack\"}\n")
+			.unwrap();
+	});
+
+	let mut subscription = Client::connect_to(&socket_path)
+		.unwrap()
+		.subscribe()
+		.unwrap();
+
+	assert!(subscription.next().is_none());
+}
@@ -0,0 +1,126 @@
+//! Shell configuration edits in a temporary home.
+use godwit::env::{self, Var};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Guards the home directory, shared by the whole process.
+static HOME: Mutex<()> = Mutex::new(());
+
+/// Original content of the test bash configuration, not valid UTF-8.
+const BASHRC: &[u8] = b"alias ll='ls -l'\nexport PS1='\xff$ '\n";
+
+/// Home directory holding a bash configuration, set as `HOME` for as long as
+/// the guard lives.
+struct Home {
+	_guard: MutexGuard<'static, ()>,
+	dir: TempDir,
+}
+
+impl Home {
+	/// Sets up a new home directory.
+	fn new() -> Self {
+		let guard = HOME.lock().unwrap_or_else(|e| e.into_inner());
+		let dir = tempfile::tempdir().unwrap();
+		fs::write(dir.path().join(".bashrc"), BASHRC).unwrap();
+		std::env::set_var("HOME", dir.path());
+
+		Home {
+			_guard: guard,
+			dir: dir,
+		}
+	}
+
+	/// Returns the bash configuration.
+	fn bashrc(&self) -> PathBuf {
+		self.dir.path().join(".bashrc")
+	}
+
+	/// Reads the bash configuration.
+	fn read_bashrc(&self) -> Vec<u8> {
+		fs::read(self.bashrc()).unwrap()
+	}
+}
+
+#[test]
+fn block_rewrite_is_idempotent() {
+	let home = Home::new();
+
+	env::set_env_var(Var::Custom(String::from("FOO")), "bar").unwrap();
+	let written = home.read_bashrc();
+
+	env::set_env_var(Var::Custom(String::from("FOO")), "bar").unwrap();
+	assert_eq!(home.read_bashrc(), written);
+
+	let expected = [
+		BASHRC,
+		b"\n# >>> Added by Godwit >>>\nexport FOO='bar'\n# <<< Added by Godwit <<<\n",
+	]
+	.concat();
+	assert_eq!(written, expected);
+
+	env::set_env_var(Var::Custom(String::from("FOO")), "baz").unwrap();
+	env::unset_env_var(Var::Custom(String::from("FOO"))).unwrap();
+	assert_eq!(home.read_bashrc(), BASHRC);
+}
+
+#[test]
+fn edits_are_backed_up_once_per_second() {
+	let home = Home::new();
+
+	env::set_env_var(Var::Custom(String::from("FOO")), "bar").unwrap();
+	env::set_env_var(Var::Custom(String::from("FOO")), "baz").unwrap();
+
+	let backups = env::list_backups(home.bashrc()).unwrap();
+	assert_eq!(backups.len(), 1);
+	assert_eq!(fs::read(&backups[0]).unwrap(), BASHRC);
+
+	thread::sleep(Duration::from_millis(1100));
+	let edited = home.read_bashrc();
+	env::set_env_var(Var::Custom(String::from("FOO")), "qux").unwrap();
+
+	let backups = env::list_backups(home.bashrc()).unwrap();
+	assert_eq!(backups.len(), 2);
+	assert_eq!(fs::read(&backups[0]).unwrap(), BASHRC);
+	assert_eq!(fs::read(&backups[1]).unwrap(), edited);
+}
+
+#[test]
+fn restore_round_trips() {
+	let home = Home::new();
+
+	env::set_env_var(Var::Custom(String::from("FOO")), "bar").unwrap();
+	assert_ne!(home.read_bashrc(), BASHRC);
+
+	let restored = env::restore_cfg_backups().unwrap();
+	assert_eq!(restored, vec![home.bashrc()]);
+	assert_eq!(home.read_bashrc(), BASHRC);
+	assert_eq!(env::list_backups(home.bashrc()).unwrap().len(), 1);
+}
+
+#[test]
+fn dry_run_prints_a_diff_without_writing() {
+	let home = Home::new();
+
+	env::set_env_var(Var::Custom(String::from("FOO")), "bar").unwrap();
+	let edited = home.read_bashrc();
+	fs::create_dir(home.dir.path().join("completions")).unwrap();
+
+	let output = Command::new(env!("CARGO_BIN_EXE_godwit"))
+		.args(&["--dry-run", "env", "restore"])
+		.current_dir(home.dir.path())
+		.env("HOME", home.dir.path())
+		.env("GODWIT_HOME", home.dir.path().join(".godwit"))
+		.output()
+		.unwrap();
+	let stdout = String::from_utf8_lossy(&output.stdout);
+
+	assert!(stdout.contains("-export FOO='bar'"), "{}", stdout);
+	assert!(stdout.contains("-# >>> Added by Godwit >>>"), "{}", stdout);
+	assert_eq!(home.read_bashrc(), edited);
+	assert_eq!(env::list_backups(home.bashrc()).unwrap().len(), 1);
+}
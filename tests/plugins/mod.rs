@@ -0,0 +1,4 @@
+#[cfg(unix)]
+mod process;
+#[cfg(unix)]
+mod protocol;
@@ -0,0 +1,50 @@
+//! Plugin processes leaving background processes behind.
+use crate::common::{fixture, isolate};
+use godwit::errors::PluginError;
+use godwit::plugins::command::Placeholders;
+use godwit::plugins::process;
+use godwit::plugins::{self, PluginConfig};
+use std::time::{Duration, Instant};
+
+/// Returns the backgrounding plugin within a timeout.
+fn backgrounding_plugin(timeout: Option<u64>) -> PluginConfig {
+	isolate();
+
+	let exec = fixture("backgrounding-plugin");
+	let mut config = plugins::new(
+		"Backgrounding",
+		&shell_words::quote(&exec.to_string_lossy()),
+	);
+	config.timeout(timeout);
+	config
+}
+
+/// Runs the backgrounding plugin with arguments.
+fn run(config: &PluginConfig, args: &[&str]) -> Result<process::Captured, PluginError> {
+	let placeholders = Placeholders::from_args(args.iter().copied());
+	let command = config.command(&placeholders)?;
+	process::run(config, command, &placeholders)
+}
+
+#[test]
+fn exited_plugin_returns_despite_background_output() {
+	let config = backgrounding_plugin(None);
+	let started = Instant::now();
+
+	let captured = run(&config, &[]).unwrap();
+	assert!(captured.status.success());
+	assert_eq!(captured.stdout, b"started\n");
+	assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn timeout_ends_background_processes() {
+	let config = backgrounding_plugin(Some(1));
+	let started = Instant::now();
+
+	match run(&config, &["wait"]) {
+		Err(PluginError::Timeout { seconds, .. }) => assert_eq!(seconds, 1),
+		other => panic!("expected a timeout, got {:?}", other.map(|c| c.status)),
+	}
+	assert!(started.elapsed() < Duration::from_secs(10));
+}
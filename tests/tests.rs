@@ -0,0 +1,5 @@
+mod common;
+mod daemon;
+mod env;
+mod plugins;
+mod settings;